@@ -0,0 +1,148 @@
+use crate::busy_light::configuration::BusyLightConfiguration;
+use crate::busy_light::drivers::{open_driver, BusyLightDriver};
+use crate::busy_light::rules::Rgb;
+use crate::teams_ws::actions::TeamsAction;
+use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
+use crate::traits::Listener;
+use async_trait::async_trait;
+use log::info;
+use std::sync::{Arc, Mutex};
+
+struct OpenDevice {
+    driver: Box<dyn BusyLightDriver>,
+    // the rules often give the same color to different states, only changes are sent to the device
+    color: Option<Rgb>,
+}
+
+/// Drives a USB or serial busy light directly, with the colors of the busy light rules
+pub struct BusyLightDevice {
+    busy_light: Arc<BusyLightConfiguration>,
+    // opened on the first update, and again after a failure, as the light may have been unplugged
+    device: Mutex<Option<OpenDevice>>,
+}
+
+impl BusyLightDevice {
+    pub fn new(busy_light: Arc<BusyLightConfiguration>) -> Self {
+        Self {
+            busy_light,
+            device: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl Listener for BusyLightDevice {
+    fn name(&self) -> &'static str {
+        "Busy Light Device"
+    }
+
+    async fn notify_changed(&self, snapshot: &TeamsSnapshot, _: &[TeamsStateKey]) -> anyhow::Result<()> {
+        let state = self.busy_light.state(snapshot);
+        let mut device = self.device.lock().unwrap();
+
+        // left closed when failing, so it is opened again on the next try
+        let mut open_device = match device.take() {
+            Some(open_device) => open_device,
+            None => OpenDevice {
+                driver: open_driver(&self.busy_light.device)?,
+                color: None,
+            },
+        };
+
+        if open_device.color != Some(state.color) {
+            info!("Busy light is now {} ({})", state.name, state.color);
+            open_device.driver.set_color(state.color)?;
+            open_device.color = Some(state.color);
+        }
+
+        *device = Some(open_device);
+        Ok(())
+    }
+
+    async fn notify_action_failed(&self, _: &TeamsAction, _: &str) -> anyhow::Result<()> {
+        // a light has no way to show a failed action
+        Ok(())
+    }
+
+    async fn notify_teams_connected(&self, _: bool) -> anyhow::Result<()> {
+        // the presence goes offline with Teams, which the rules already turn into a color
+        Ok(())
+    }
+
+    fn reconnect(&mut self) {
+        *self.device.get_mut().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::busy_light::api::{BusyLightDevice, OpenDevice};
+    use crate::busy_light::configuration::create_busy_light_configuration;
+    use crate::busy_light::drivers::BusyLightDriver;
+    use crate::busy_light::rules::Rgb;
+    use crate::teams_ws::states::{TeamsStateKey, TeamsStates};
+    use crate::traits::Listener;
+    use anyhow::anyhow;
+    use std::sync::{Arc, Mutex};
+
+    struct RecordingDriver {
+        colors: Arc<Mutex<Vec<Rgb>>>,
+        fail: bool,
+    }
+
+    impl BusyLightDriver for RecordingDriver {
+        fn set_color(&mut self, color: Rgb) -> anyhow::Result<()> {
+            if self.fail {
+                return Err(anyhow!("unplugged"));
+            }
+
+            self.colors.lock().unwrap().push(color);
+            Ok(())
+        }
+    }
+
+    fn create_test_device(fail: bool) -> (BusyLightDevice, Arc<Mutex<Vec<Rgb>>>) {
+        let colors = Arc::new(Mutex::new(Vec::new()));
+        let driver = RecordingDriver {
+            colors: colors.clone(),
+            fail,
+        };
+        let device = BusyLightDevice {
+            busy_light: Arc::new(create_busy_light_configuration()),
+            device: Mutex::new(Some(OpenDevice {
+                driver: Box::new(driver),
+                color: None,
+            })),
+        };
+
+        (device, colors)
+    }
+
+    #[tokio::test]
+    async fn notify_changed_same_color_will_only_be_sent_once() {
+        let (device, colors) = create_test_device(false);
+        let teams_states = TeamsStates::default();
+        teams_states.update(|snapshot| snapshot.with_teams_connected(true));
+        device.notify_changed(&teams_states.snapshot(), &[]).await.unwrap();
+
+        // unread messages are not in any rule, the light stays free
+        teams_states.update(|snapshot| snapshot.with(TeamsStateKey::HasUnreadMessages, true));
+        device.notify_changed(&teams_states.snapshot(), &[]).await.unwrap();
+
+        teams_states.update(|snapshot| snapshot.with(TeamsStateKey::IsInMeeting, true));
+        device.notify_changed(&teams_states.snapshot(), &[]).await.unwrap();
+
+        assert_eq!(
+            *colors.lock().unwrap(),
+            vec![Rgb::new(0, 255, 0), Rgb::new(255, 255, 0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn notify_changed_failing_driver_will_close_device() {
+        let (device, _) = create_test_device(true);
+
+        assert!(device.notify_changed(&TeamsStates::default().snapshot(), &[]).await.is_err());
+        assert!(device.device.lock().unwrap().is_none());
+    }
+}
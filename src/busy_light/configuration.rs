@@ -0,0 +1,113 @@
+use crate::busy_light::rules::{default_rules, BusyLightRule, BusyLightState};
+use crate::home_assistant::configuration::{create_ha_entity, HaEntity};
+use crate::teams_ws::states::TeamsSnapshot;
+use log::warn;
+
+pub const BUSY_LIGHT: &str = "Busy Light";
+pub const BUSY_LIGHT_ENABLED: &str = "Enabled";
+// followed by the name of the light state, e.g. `Busy Light Rule - Muted`
+pub const BUSY_LIGHT_RULE: &str = "Busy Light Rule - ";
+pub const BUSY_LIGHT_WHEN: &str = "When";
+pub const BUSY_LIGHT_COLOR: &str = "Color";
+pub const BUSY_LIGHT_DEVICE: &str = "Busy Light Device";
+pub const BUSY_LIGHT_DEVICE_DRIVER: &str = "Driver";
+pub const BUSY_LIGHT_DEVICE_PORT: &str = "Port";
+pub const BUSY_LIGHT_DEVICE_BAUD_RATE: &str = "Baud Rate";
+pub const BUSY_LIGHT_DEVICE_BAUD_RATE_DEFAULT: u32 = 9600;
+
+pub struct BusyLightDeviceConfiguration {
+    // serial, luxafor or blink1, no device is driven when empty
+    pub driver: String,
+    // only used by the serial driver, e.g. COM3 or /dev/ttyACM0
+    pub port: String,
+    pub baud_rate: u32,
+}
+
+// name, conditions and color, as written in the ini file
+pub struct BusyLightRuleValues {
+    pub name: String,
+    pub when: String,
+    pub color: String,
+}
+
+pub struct BusyLightConfiguration {
+    pub enabled: bool,
+    // the HA keys (ID, Friendly Name, Icon On/Off) are in the Busy Light section
+    pub ha_entity: HaEntity,
+    // the first rule matching the Teams states gives the light state
+    pub rules: Vec<BusyLightRule>,
+    // saved back unchanged, so a typo does not remove the rule from the file
+    pub rule_values: Vec<BusyLightRuleValues>,
+    // driven with the same rules, whether the state is published to MQTT and HA or not
+    pub device: BusyLightDeviceConfiguration,
+}
+
+impl BusyLightConfiguration {
+    /// The configured rules replace the default ones, a rule that cannot be parsed is left out of the evaluation
+    pub fn set_rules(&mut self, rule_values: Vec<BusyLightRuleValues>) {
+        self.rules = rule_values
+            .iter()
+            .filter_map(|rule| {
+                BusyLightRule::new(&rule.name, &rule.when, &rule.color)
+                    .map_err(|error| warn!("Ignoring the busy light rule: {:#}", error))
+                    .ok()
+            })
+            .collect();
+        self.rule_values = rule_values;
+    }
+
+    pub fn state(&self, snapshot: &TeamsSnapshot) -> BusyLightState {
+        BusyLightState::from_rules(&self.rules, snapshot)
+    }
+}
+
+pub fn create_busy_light_configuration() -> BusyLightConfiguration {
+    let rules = default_rules();
+    let rule_values = rules
+        .iter()
+        .map(|rule| BusyLightRuleValues {
+            name: rule.name.clone(),
+            when: rule.when(),
+            color: rule.color.to_string(),
+        })
+        .collect();
+
+    BusyLightConfiguration {
+        enabled: false,
+        ha_entity: create_ha_entity(
+            "sensor.teams_busy_light",
+            "Teams Busy Light",
+            "mdi:lightbulb-on",
+            "mdi:lightbulb-off",
+        ),
+        rules,
+        rule_values,
+        device: BusyLightDeviceConfiguration {
+            driver: "".to_string(),
+            port: "".to_string(),
+            baud_rate: BUSY_LIGHT_DEVICE_BAUD_RATE_DEFAULT,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::busy_light::configuration::{create_busy_light_configuration, BusyLightRuleValues};
+
+    #[test]
+    fn set_rules_invalid_rule_will_be_kept_but_not_evaluated() {
+        let mut busy_light = create_busy_light_configuration();
+        let rule_values = |name: &str, when: &str| BusyLightRuleValues {
+            name: name.to_string(),
+            when: when.to_string(),
+            color: "255, 0, 0".to_string(),
+        };
+
+        busy_light.set_rules(vec![rule_values("Muted", "is_mutd"), rule_values("Busy", "presence=Busy")]);
+
+        assert_eq!(busy_light.rules.len(), 1);
+        assert_eq!(busy_light.rules[0].name, "Busy");
+        assert_eq!(busy_light.rule_values.len(), 2);
+        assert_eq!(busy_light.rule_values[0].when, "is_mutd");
+    }
+}
@@ -0,0 +1,98 @@
+use crate::busy_light::drivers::BusyLightDriver;
+use crate::busy_light::rules::Rgb;
+use anyhow::Context;
+use hidapi::{HidApi, HidDevice};
+
+// sets every LED of the flag at once
+const LUXAFOR_STATIC_COLOR: u8 = 1;
+const LUXAFOR_ALL_LEDS: u8 = 0xFF;
+// "fade to color" with no fade time
+const BLINK1_SET_COLOR: u8 = b'n';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HidModel {
+    Luxafor,
+    Blink1,
+}
+
+impl HidModel {
+    fn name(&self) -> &'static str {
+        match self {
+            HidModel::Luxafor => "Luxafor",
+            HidModel::Blink1 => "blink(1)",
+        }
+    }
+
+    // vendor and product ids
+    fn ids(&self) -> (u16, u16) {
+        match self {
+            HidModel::Luxafor => (0x04D8, 0xF372),
+            HidModel::Blink1 => (0x27B8, 0x01ED),
+        }
+    }
+
+    // both start with the report id expected by hidapi
+    fn report(&self, color: Rgb) -> [u8; 9] {
+        match self {
+            HidModel::Luxafor => [
+                0,
+                LUXAFOR_STATIC_COLOR,
+                LUXAFOR_ALL_LEDS,
+                color.red,
+                color.green,
+                color.blue,
+                0,
+                0,
+                0,
+            ],
+            HidModel::Blink1 => [1, BLINK1_SET_COLOR, color.red, color.green, color.blue, 0, 0, 0, 0],
+        }
+    }
+}
+
+/// USB lights that are driven without their vendor's application
+pub struct HidDriver {
+    model: HidModel,
+    device: HidDevice,
+}
+
+impl HidDriver {
+    pub fn open(model: HidModel) -> anyhow::Result<Self> {
+        let (vendor_id, product_id) = model.ids();
+        let device = HidApi::new()
+            .and_then(|api| api.open(vendor_id, product_id))
+            .with_context(|| format!("Unable to find the {} busy light", model.name()))?;
+
+        Ok(Self { model, device })
+    }
+}
+
+impl BusyLightDriver for HidDriver {
+    fn set_color(&mut self, color: Rgb) -> anyhow::Result<()> {
+        let report = self.model.report(color);
+
+        // the blink(1) only listens to feature reports
+        match self.model {
+            HidModel::Luxafor => {
+                self.device.write(&report)?;
+            }
+            HidModel::Blink1 => self.device.send_feature_report(&report)?,
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::busy_light::drivers::hid::HidModel;
+    use crate::busy_light::rules::Rgb;
+
+    #[test]
+    fn report_will_follow_device_protocol() {
+        let color = Rgb::new(255, 165, 0);
+
+        assert_eq!(HidModel::Luxafor.report(color), [0, 1, 0xFF, 255, 165, 0, 0, 0, 0]);
+        assert_eq!(HidModel::Blink1.report(color), [1, b'n', 255, 165, 0, 0, 0, 0, 0]);
+    }
+}
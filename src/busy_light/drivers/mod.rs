@@ -0,0 +1,36 @@
+use crate::busy_light::configuration::BusyLightDeviceConfiguration;
+use crate::busy_light::drivers::hid::{HidDriver, HidModel};
+use crate::busy_light::drivers::serial::SerialDriver;
+use crate::busy_light::rules::Rgb;
+use anyhow::anyhow;
+
+mod hid;
+mod serial;
+
+const DRIVER_SERIAL: &str = "serial";
+const DRIVER_LUXAFOR: &str = "luxafor";
+const DRIVER_BLINK1: &str = "blink1";
+
+/// A light plugged into the PC, a new device only needs to implement this and be added to `open_driver`
+pub trait BusyLightDriver: Send {
+    fn set_color(&mut self, color: Rgb) -> anyhow::Result<()>;
+}
+
+pub fn open_driver(configuration: &BusyLightDeviceConfiguration) -> anyhow::Result<Box<dyn BusyLightDriver>> {
+    let driver: Box<dyn BusyLightDriver> = match configuration.driver.to_lowercase().as_str() {
+        DRIVER_SERIAL => Box::new(SerialDriver::open(&configuration.port, configuration.baud_rate)?),
+        DRIVER_LUXAFOR => Box::new(HidDriver::open(HidModel::Luxafor)?),
+        DRIVER_BLINK1 => Box::new(HidDriver::open(HidModel::Blink1)?),
+        driver => {
+            return Err(anyhow!(
+                "Unknown busy light driver '{}', expected {}, {} or {}",
+                driver,
+                DRIVER_SERIAL,
+                DRIVER_LUXAFOR,
+                DRIVER_BLINK1
+            ))
+        }
+    };
+
+    Ok(driver)
+}
@@ -0,0 +1,59 @@
+use crate::busy_light::drivers::BusyLightDriver;
+use crate::busy_light::rules::Rgb;
+use anyhow::Context;
+use serialport::SerialPort;
+use std::io::Write;
+use std::time::Duration;
+
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// DIY lights, e.g. an Arduino, receiving one `#RRGGBB` line per color
+pub struct SerialDriver {
+    port: Box<dyn SerialPort>,
+}
+
+impl SerialDriver {
+    pub fn open(path: &str, baud_rate: u32) -> anyhow::Result<Self> {
+        let port = serialport::new(path, baud_rate)
+            .timeout(WRITE_TIMEOUT)
+            .open()
+            .with_context(|| format!("Unable to open the busy light on {}", path))?;
+
+        Ok(Self::new(port))
+    }
+
+    pub fn new(port: Box<dyn SerialPort>) -> Self {
+        Self { port }
+    }
+}
+
+impl BusyLightDriver for SerialDriver {
+    fn set_color(&mut self, color: Rgb) -> anyhow::Result<()> {
+        writeln!(self.port, "#{:02X}{:02X}{:02X}", color.red, color.green, color.blue)?;
+        self.port.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg(unix)]
+mod tests {
+    use crate::busy_light::drivers::serial::SerialDriver;
+    use crate::busy_light::drivers::BusyLightDriver;
+    use crate::busy_light::rules::Rgb;
+    use serialport::TTYPort;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn set_color_will_write_hex_line_to_pty() {
+        let (master, slave) = TTYPort::pair().unwrap();
+        let mut driver = SerialDriver::new(Box::new(slave));
+
+        driver.set_color(Rgb::new(255, 165, 0)).unwrap();
+        driver.set_color(Rgb::OFF).unwrap();
+
+        let mut lines = BufReader::new(master).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "#FFA500");
+        assert_eq!(lines.next().unwrap().unwrap(), "#000000");
+    }
+}
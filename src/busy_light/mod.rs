@@ -0,0 +1,4 @@
+pub mod api;
+pub mod configuration;
+mod drivers;
+pub mod rules;
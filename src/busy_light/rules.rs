@@ -0,0 +1,276 @@
+use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
+use anyhow::{anyhow, Context};
+use serde_json::{json, Value};
+use std::fmt;
+use std::str::FromStr;
+
+// the light is turned off when no rule matches
+const NO_MATCH_NAME: &str = "Off";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Rgb {
+    pub const OFF: Rgb = Rgb::new(0, 0, 0);
+
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    pub fn is_off(&self) -> bool {
+        *self == Rgb::OFF
+    }
+}
+
+/// Written as `255, 165, 0` in the configuration
+impl FromStr for Rgb {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let channels = value
+            .split(',')
+            .map(|channel| channel.trim().parse::<u8>())
+            .collect::<Result<Vec<u8>, _>>()
+            .with_context(|| format!("Invalid color '{}', expected 'red, green, blue' from 0 to 255", value))?;
+
+        match channels[..] {
+            [red, green, blue] => Ok(Rgb::new(red, green, blue)),
+            _ => Err(anyhow!("Invalid color '{}', expected 'red, green, blue' from 0 to 255", value)),
+        }
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}, {}", self.red, self.green, self.blue)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Condition {
+    // `is_muted` or `!is_muted`
+    Is(TeamsStateKey, bool),
+    // `presence=In Call`, for the states that are not on/off
+    Equals(TeamsStateKey, String),
+}
+
+impl Condition {
+    fn matches(&self, snapshot: &TeamsSnapshot) -> bool {
+        match self {
+            Condition::Is(key, expected) => snapshot.get(*key) == *expected,
+            Condition::Equals(key, expected) => snapshot.state_str(*key).eq_ignore_ascii_case(expected),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let find_key = |name: &str| {
+            TeamsStateKey::from_name(name.trim()).ok_or_else(|| anyhow!("Unknown state '{}'", name.trim()))
+        };
+
+        if let Some((name, expected)) = value.split_once('=') {
+            let key = find_key(name)?;
+
+            if key.is_binary() {
+                return Err(anyhow!("'{}' is on/off, use '{}' or '!{}'", key.name(), key.name(), key.name()));
+            }
+
+            return Ok(Condition::Equals(key, expected.trim().to_string()));
+        }
+
+        let (name, expected) = match value.strip_prefix('!') {
+            Some(name) => (name, false),
+            None => (value, true),
+        };
+        let key = find_key(name)?;
+
+        if !key.is_binary() {
+            return Err(anyhow!("'{}' is not on/off, use '{}=<value>'", key.name(), key.name()));
+        }
+
+        Ok(Condition::Is(key, expected))
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Is(key, true) => f.write_str(key.name()),
+            Condition::Is(key, false) => write!(f, "!{}", key.name()),
+            Condition::Equals(key, expected) => write!(f, "{}={}", key.name(), expected),
+        }
+    }
+}
+
+/// Named light state, used when all of its conditions match
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusyLightRule {
+    pub name: String,
+    conditions: Vec<Condition>,
+    pub color: Rgb,
+}
+
+impl BusyLightRule {
+    /// `when` is a comma separated list of conditions, e.g. `is_in_meeting, !is_muted, presence=DND`, an empty one
+    /// always matches
+    pub fn new(name: &str, when: &str, color: &str) -> anyhow::Result<Self> {
+        let conditions = when
+            .split(',')
+            .map(str::trim)
+            .filter(|condition| !condition.is_empty())
+            .map(Condition::from_str)
+            .collect::<anyhow::Result<Vec<Condition>>>()
+            .with_context(|| format!("Invalid busy light rule '{}'", name))?;
+        let color = color
+            .parse()
+            .with_context(|| format!("Invalid busy light rule '{}'", name))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            conditions,
+            color,
+        })
+    }
+
+    pub fn when(&self) -> String {
+        let conditions: Vec<String> = self.conditions.iter().map(Condition::to_string).collect();
+        conditions.join(", ")
+    }
+
+    fn matches(&self, snapshot: &TeamsSnapshot) -> bool {
+        self.conditions.iter().all(|condition| condition.matches(snapshot))
+    }
+}
+
+/// Used until the rules are configured, the first matching one wins
+pub fn default_rules() -> Vec<BusyLightRule> {
+    [
+        // the meeting states are stale once Teams is offline
+        ("Off", "presence=Offline", "0, 0, 0"),
+        ("On Camera", "is_in_meeting, is_video_on", "255, 0, 0"),
+        ("Muted", "is_in_meeting, is_muted", "255, 165, 0"),
+        ("In Call", "is_in_meeting", "255, 255, 0"),
+        ("Do Not Disturb", "presence=DND", "128, 0, 128"),
+        ("Busy", "presence=Busy", "255, 0, 0"),
+        ("Away", "presence=Away", "0, 0, 255"),
+        ("Free", "", "0, 255, 0"),
+    ]
+    .into_iter()
+    .map(|(name, when, color)| BusyLightRule::new(name, when, color).expect("the default rules are valid"))
+    .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BusyLightState {
+    pub name: String,
+    pub color: Rgb,
+}
+
+impl BusyLightState {
+    pub fn from_rules(rules: &[BusyLightRule], snapshot: &TeamsSnapshot) -> Self {
+        match rules.iter().find(|rule| rule.matches(snapshot)) {
+            Some(rule) => Self {
+                name: rule.name.clone(),
+                color: rule.color,
+            },
+            None => Self {
+                name: NO_MATCH_NAME.to_string(),
+                color: Rgb::OFF,
+            },
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "state": self.name,
+            "rgb_color": [self.color.red, self.color.green, self.color.blue],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::busy_light::rules::{default_rules, BusyLightRule, BusyLightState, Rgb};
+    use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey, TeamsStates};
+
+    fn connected_snapshot(change: impl FnOnce(TeamsSnapshot) -> TeamsSnapshot) -> TeamsSnapshot {
+        let teams_states = TeamsStates::default();
+        teams_states.update(|snapshot| change(snapshot.with_teams_connected(true)));
+        teams_states.snapshot()
+    }
+
+    #[test]
+    fn from_rules_first_matching_rule_will_win() {
+        let snapshot = connected_snapshot(|snapshot| {
+            snapshot
+                .with(TeamsStateKey::IsInMeeting, true)
+                .with(TeamsStateKey::IsVideoOn, true)
+                .with(TeamsStateKey::IsMuted, true)
+        });
+
+        let state = BusyLightState::from_rules(&default_rules(), &snapshot);
+
+        assert_eq!(state.name, "On Camera");
+        assert_eq!(state.color, Rgb::new(255, 0, 0));
+    }
+
+    #[test]
+    fn from_rules_negated_condition_will_match_off_state() {
+        let rules = vec![BusyLightRule::new("Talking", "is_in_meeting, !is_muted", "255, 0, 0").unwrap()];
+        let snapshot = connected_snapshot(|snapshot| snapshot.with(TeamsStateKey::IsInMeeting, true));
+
+        assert_eq!(BusyLightState::from_rules(&rules, &snapshot).name, "Talking");
+    }
+
+    #[test]
+    fn from_rules_no_match_will_be_off() {
+        let rules = vec![BusyLightRule::new("Muted", "is_muted", "255, 165, 0").unwrap()];
+
+        let state = BusyLightState::from_rules(&rules, &TeamsSnapshot::default());
+
+        assert_eq!(state.name, "Off");
+        assert!(state.color.is_off());
+    }
+
+    #[test]
+    fn from_rules_presence_condition_will_ignore_case() {
+        let rules = vec![BusyLightRule::new("Calling", "presence=in call", "255, 255, 0").unwrap()];
+        let snapshot = connected_snapshot(|snapshot| snapshot.with(TeamsStateKey::IsInMeeting, true));
+
+        assert_eq!(BusyLightState::from_rules(&rules, &snapshot).name, "Calling");
+    }
+
+    #[test]
+    fn new_invalid_rule_will_fail() {
+        assert!(BusyLightRule::new("Happy", "is_happy", "0, 255, 0").is_err());
+        assert!(BusyLightRule::new("Muted", "is_muted=on", "0, 255, 0").is_err());
+        assert!(BusyLightRule::new("Muted", "is_muted", "0, 256, 0").is_err());
+        assert!(BusyLightRule::new("Muted", "is_muted", "0, 255").is_err());
+    }
+
+    #[test]
+    fn when_will_round_trip() {
+        let rule = BusyLightRule::new("Talking", "is_in_meeting,!is_muted , presence=DND", "1,2,3").unwrap();
+
+        assert_eq!(rule.when(), "is_in_meeting, !is_muted, presence=DND");
+        assert_eq!(rule.color.to_string(), "1, 2, 3");
+    }
+
+    #[test]
+    fn to_json_will_hold_state_and_rgb_color() {
+        let state = BusyLightState {
+            name: "Muted".to_string(),
+            color: Rgb::new(255, 165, 0),
+        };
+
+        assert_eq!(state.to_json()["state"], "Muted");
+        assert_eq!(state.to_json()["rgb_color"], serde_json::json!([255, 165, 0]));
+    }
+}
@@ -1,24 +1,49 @@
+use crate::busy_light::configuration::{
+    create_busy_light_configuration, BusyLightConfiguration, BusyLightRuleValues, BUSY_LIGHT,
+    BUSY_LIGHT_COLOR, BUSY_LIGHT_DEVICE, BUSY_LIGHT_DEVICE_BAUD_RATE,
+    BUSY_LIGHT_DEVICE_BAUD_RATE_DEFAULT, BUSY_LIGHT_DEVICE_DRIVER, BUSY_LIGHT_DEVICE_PORT,
+    BUSY_LIGHT_ENABLED, BUSY_LIGHT_RULE, BUSY_LIGHT_WHEN,
+};
 use crate::home_assistant::configuration::{
-    create_ha_configuration, HaConfiguration, HaEntity, HA_BACKGROUND_BLURRED, HA_FRIENDLY_NAME,
-    HA_HAND_RAISED, HA_ICON_OFF, HA_ICON_ON, HA_ID, HA_IN_A_MEETING, HA_LONG_LIVE_TOKEN, HA_MUTED,
-    HA_RECORDING, HA_SHARING, HA_UNREAD_MESSAGES, HA_URL, HA_VIDEO_ON, HOME_ASSISTANT,
+    create_ha_configuration, ha_section, HaConfiguration, HaEntity, HA_ATTRIBUTE, HA_DEVICE_CLASS,
+    HA_FRIENDLY_NAME, HA_ICON_OFF, HA_ICON_ON, HA_ID, HA_LONG_LIVE_TOKEN, HA_MODE, HA_STATE_OFF,
+    HA_STATE_ON, HA_URL, HOME_ASSISTANT,
+};
+use crate::http_api::configuration::{
+    create_http_api_configuration, HttpApiConfiguration, HTTP_API, HTTP_API_BEARER_TOKEN,
+    HTTP_API_ENABLED, HTTP_API_PORT, HTTP_API_PORT_DEFAULT,
 };
+use crate::instance::create_instance;
 use crate::mqtt::configuration::{
-    create_mqtt_configuration, MqttConfiguration, MQTT, MQTT_BACKGROUND_BLURRED, MQTT_ENTITIES,
-    MQTT_HAND_RAISED, MQTT_MEETING, MQTT_MUTED, MQTT_PASSWORD, MQTT_PORT, MQTT_PORT_DEFAULT,
-    MQTT_RECORDING, MQTT_SHARING, MQTT_TOPIC, MQTT_UNREAD_MESSAGES, MQTT_URL, MQTT_USERNAME,
-    MQTT_VIDEO,
+    create_mqtt_configuration, mqtt_entity_config_name, MqttConfiguration, MQTT,
+    MQTT_COMMAND_TOPIC, MQTT_DISCOVERY, MQTT_DISCOVERY_PREFIX, MQTT_ENTITIES, MQTT_NODE_ID,
+    MQTT_PASSWORD, MQTT_PORT, MQTT_PORT_DEFAULT, MQTT_TOPIC, MQTT_URL, MQTT_USERNAME,
+};
+use crate::presence::configuration::{
+    create_presence_configuration, PresenceConfiguration, PRESENCE, PRESENCE_PRECEDENCE,
+};
+use crate::presence::fusion::PresencePrecedence;
+use crate::teams_log::configuration::{
+    create_teams_log_configuration, TeamsLogConfiguration, TEAMS_LOG, TEAMS_LOG_ENABLED,
+    TEAMS_LOG_PATH,
 };
 use crate::teams_ws::configuration::{
     create_teams_configuration, TeamsConfiguration, TEAMS, TEAMS_API_TOKEN, TEAMS_URL,
 };
+use crate::teams_ws::states::TeamsStateKey;
 use crate::utils::{decrypt_if_needed, encrypt};
+use crate::webhook::configuration::{
+    create_webhook_configuration, WebhookConfiguration, WEBHOOK, WEBHOOK_BODY_TEMPLATE, WEBHOOK_HEADER,
+    WEBHOOK_HMAC_SECRET, WEBHOOK_METHOD, WEBHOOK_URL,
+};
 use ini::Ini;
 use log::{error, info};
 use std::fs;
 
 const GENERAL: &str = "General";
 const GEN_CONF_VERSION: &str = "Configuration Version";
+// prefixes the HA entities, the MQTT topics and the MQTT client id, for several PCs to share the same HA and broker
+const GEN_INSTANCE_NAME: &str = "Instance Name";
 const GEN_CONF_VERSION_CURRENT: u32 = 1;
 // Anything below this will result in copying the configuration as a backup as there are breaking changes
 const GEN_CONF_VERSION_CUTOFF: u32 = 1;
@@ -28,6 +53,12 @@ pub struct Configuration {
     pub ha: HaConfiguration,
     pub teams: TeamsConfiguration,
     pub mqtt: MqttConfiguration,
+    pub http_api: HttpApiConfiguration,
+    pub teams_log: TeamsLogConfiguration,
+    pub presence: PresenceConfiguration,
+    pub busy_light: BusyLightConfiguration,
+    pub webhooks: Vec<WebhookConfiguration>,
+    pub instance_name: String,
     pub version: u32,
 }
 
@@ -38,6 +69,8 @@ pub fn get_configuration(save_configuration: bool) -> Configuration {
     if save_configuration {
         save_ha_configuration(&conf);
     };
+    // applied after saving, so the file keeps the ids and topics without the prefix
+    apply_instance_name(&mut conf);
     conf
 }
 
@@ -47,10 +80,68 @@ fn load_entity(ha_entity: &mut HaEntity, config_name: &str, config_value: String
         HA_FRIENDLY_NAME => ha_entity.friendly_name = config_value,
         HA_ICON_ON => ha_entity.icons.on = config_value,
         HA_ICON_OFF => ha_entity.icons.off = config_value,
+        HA_STATE_ON => ha_entity.states.on = config_value,
+        HA_STATE_OFF => ha_entity.states.off = config_value,
+        HA_DEVICE_CLASS => ha_entity.device_class = config_value,
+        _ => {
+            if let Some(attribute) = config_name.strip_prefix(HA_ATTRIBUTE) {
+                ha_entity.attributes.push((attribute.to_string(), config_value));
+            }
+        }
+    }
+}
+
+fn load_busy_light_rule(rules: &mut Vec<BusyLightRuleValues>, name: &str, config_name: &str, config_value: String) {
+    let index = match rules.iter().position(|rule| rule.name == name) {
+        Some(index) => index,
+        None => {
+            rules.push(BusyLightRuleValues {
+                name: name.to_string(),
+                when: "".to_string(),
+                color: "".to_string(),
+            });
+            rules.len() - 1
+        }
+    };
+
+    match config_name {
+        BUSY_LIGHT_WHEN => rules[index].when = config_value,
+        BUSY_LIGHT_COLOR => rules[index].color = config_value,
         _ => { /* We just ignore incorrect configs */ }
     }
 }
 
+fn load_webhook(webhooks: &mut Vec<WebhookConfiguration>, name: &str, config_name: &str, config_value: String) {
+    let index = match webhooks.iter().position(|webhook| webhook.name == name) {
+        Some(index) => index,
+        None => {
+            webhooks.push(create_webhook_configuration(name));
+            webhooks.len() - 1
+        }
+    };
+    let webhook = &mut webhooks[index];
+
+    match config_name {
+        WEBHOOK_URL => webhook.url = config_value,
+        WEBHOOK_METHOD => webhook.method = config_value,
+        WEBHOOK_HMAC_SECRET => webhook.hmac_secret = decrypt_if_needed(&config_value),
+        WEBHOOK_BODY_TEMPLATE => webhook.body_template = config_value,
+        _ => {
+            if let Some(header) = config_name.strip_prefix(WEBHOOK_HEADER) {
+                webhook.headers.push((header.to_string(), config_value));
+            }
+        }
+    }
+}
+
+fn apply_instance_name(conf: &mut Configuration) {
+    let instance = create_instance(&conf.instance_name);
+
+    instance.prefix_ha_entity(&mut conf.busy_light.ha_entity);
+    conf.ha.set_instance(instance.clone());
+    conf.mqtt.set_instance(instance);
+}
+
 fn load_configuration(conf: &mut Configuration) {
     let i = Ini::load_from_file(INI_FILE_NAME).unwrap_or_else(|err| {
         info!(
@@ -60,6 +151,8 @@ fn load_configuration(conf: &mut Configuration) {
         return Ini::new();
     });
 
+    let mut busy_light_rules = Vec::new();
+
     for (sec, prop) in i.iter() {
         for (k, v) in prop.iter() {
             if v.is_empty() {
@@ -71,31 +164,18 @@ fn load_configuration(conf: &mut Configuration) {
             match sec {
                 Some(GENERAL) => match k {
                     GEN_CONF_VERSION => conf.version = v.parse::<u32>().unwrap_or(0),
+                    GEN_INSTANCE_NAME => conf.instance_name = v.to_string(),
                     &_ => {}
                 },
                 Some(HOME_ASSISTANT) => match k {
                     HA_LONG_LIVE_TOKEN => conf.ha.long_live_token = decrypt_if_needed(v),
                     HA_URL => conf.ha.url = v.to_string(),
+                    HA_MODE => match v.parse() {
+                        Ok(mode) => conf.ha.mode = mode,
+                        Err(error) => error!("Invalid Home Assistant mode, using the default one: {}", error),
+                    },
                     _ => { /* We just ignore incorrect configs */ }
                 },
-                Some(HA_MUTED) => load_entity(&mut conf.ha.entities.is_muted, k, v_string),
-                Some(HA_VIDEO_ON) => load_entity(&mut conf.ha.entities.is_video_on, k, v_string),
-                Some(HA_HAND_RAISED) => {
-                    load_entity(&mut conf.ha.entities.is_hand_raised, k, v_string)
-                }
-                Some(HA_IN_A_MEETING) => {
-                    load_entity(&mut conf.ha.entities.is_in_meeting, k, v_string)
-                }
-                Some(HA_RECORDING) => {
-                    load_entity(&mut conf.ha.entities.is_recording_on, k, v_string)
-                }
-                Some(HA_BACKGROUND_BLURRED) => {
-                    load_entity(&mut conf.ha.entities.is_background_blurred, k, v_string)
-                }
-                Some(HA_SHARING) => load_entity(&mut conf.ha.entities.is_sharing, k, v_string),
-                Some(HA_UNREAD_MESSAGES) => {
-                    load_entity(&mut conf.ha.entities.has_unread_messages, k, v_string)
-                }
                 Some(TEAMS) => match k {
                     TEAMS_URL => conf.teams.url = v.to_string(),
                     TEAMS_API_TOKEN => conf.teams.api_token = decrypt_if_needed(v),
@@ -105,28 +185,80 @@ fn load_configuration(conf: &mut Configuration) {
                     MQTT_URL => conf.mqtt.set_url(v.to_string()),
                     MQTT_PORT => conf.mqtt.port = v.parse().unwrap_or(MQTT_PORT_DEFAULT),
                     MQTT_TOPIC => conf.mqtt.topic = v.to_string(),
+                    MQTT_COMMAND_TOPIC => conf.mqtt.command_topic = v.to_string(),
+                    MQTT_DISCOVERY => conf.mqtt.discovery = v.parse().unwrap_or(true),
+                    MQTT_DISCOVERY_PREFIX => conf.mqtt.discovery_prefix = v.to_string(),
+                    MQTT_NODE_ID => conf.mqtt.node_id = v.to_string(),
                     MQTT_USERNAME => conf.mqtt.username = v.to_string(),
                     MQTT_PASSWORD => conf.mqtt.password = decrypt_if_needed(v),
                     _ => { /* We just ignore incorrect configs */ }
                 },
-                Some(MQTT_ENTITIES) => match k {
-                    MQTT_MUTED => conf.mqtt.mqtt_entities.muted = v.to_string(),
-                    MQTT_VIDEO => conf.mqtt.mqtt_entities.video = v.to_string(),
-                    MQTT_HAND_RAISED => conf.mqtt.mqtt_entities.hand_raised = v.to_string(),
-                    MQTT_MEETING => conf.mqtt.mqtt_entities.meeting = v.to_string(),
-                    MQTT_RECORDING => conf.mqtt.mqtt_entities.recording = v.to_string(),
-                    MQTT_BACKGROUND_BLURRED => {
-                        conf.mqtt.mqtt_entities.background_blurred = v.to_string()
+                Some(HTTP_API) => match k {
+                    HTTP_API_ENABLED => conf.http_api.enabled = v.parse().unwrap_or(false),
+                    HTTP_API_PORT => conf.http_api.port = v.parse().unwrap_or(HTTP_API_PORT_DEFAULT),
+                    HTTP_API_BEARER_TOKEN => conf.http_api.bearer_token = decrypt_if_needed(v),
+                    _ => { /* We just ignore incorrect configs */ }
+                },
+                Some(TEAMS_LOG) => match k {
+                    TEAMS_LOG_ENABLED => conf.teams_log.enabled = v.parse().unwrap_or(false),
+                    TEAMS_LOG_PATH => conf.teams_log.path = v.to_string(),
+                    _ => { /* We just ignore incorrect configs */ }
+                },
+                Some(PRESENCE) => match k {
+                    PRESENCE_PRECEDENCE => match PresencePrecedence::parse(v) {
+                        Ok(precedence) => conf.presence.precedence = precedence,
+                        Err(error) => error!("Invalid presence precedence, using the default one: {}", error),
+                    },
+                    _ => { /* We just ignore incorrect configs */ }
+                },
+                Some(BUSY_LIGHT) => match k {
+                    BUSY_LIGHT_ENABLED => conf.busy_light.enabled = v.parse().unwrap_or(false),
+                    _ => load_entity(&mut conf.busy_light.ha_entity, k, v_string),
+                },
+                Some(BUSY_LIGHT_DEVICE) => match k {
+                    BUSY_LIGHT_DEVICE_DRIVER => conf.busy_light.device.driver = v.to_string(),
+                    BUSY_LIGHT_DEVICE_PORT => conf.busy_light.device.port = v.to_string(),
+                    BUSY_LIGHT_DEVICE_BAUD_RATE => {
+                        conf.busy_light.device.baud_rate = v.parse().unwrap_or(BUSY_LIGHT_DEVICE_BAUD_RATE_DEFAULT)
                     }
-                    MQTT_SHARING => conf.mqtt.mqtt_entities.sharing = v.to_string(),
-                    MQTT_UNREAD_MESSAGES => conf.mqtt.mqtt_entities.unread_messages = v.to_string(),
                     _ => { /* We just ignore incorrect configs */ }
                 },
+                Some(section) if section.starts_with(BUSY_LIGHT_RULE) => {
+                    let name = &section[BUSY_LIGHT_RULE.len()..];
+                    load_busy_light_rule(&mut busy_light_rules, name, k, v_string);
+                }
+                Some(section) if section.starts_with(WEBHOOK) => {
+                    let name = &section[WEBHOOK.len()..];
+                    load_webhook(&mut conf.webhooks, name, k, v_string);
+                }
+                Some(MQTT_ENTITIES) => {
+                    let key = TeamsStateKey::ALL
+                        .into_iter()
+                        .find(|key| mqtt_entity_config_name(*key) == k);
+
+                    if let Some(key) = key {
+                        conf.mqtt.mqtt_entities.set(key, v_string);
+                    }
+                }
+                Some(section) => {
+                    let key = TeamsStateKey::ALL
+                        .into_iter()
+                        .find(|key| ha_section(*key) == section);
+
+                    if let Some(key) = key {
+                        load_entity(conf.ha.entities.get_mut(key), k, v_string);
+                    }
+                }
                 _ => { /* We just ignore incorrect configs */ }
             }
         }
     }
 
+    // the rule is only parsed once both its conditions and color are known
+    if !busy_light_rules.is_empty() {
+        conf.busy_light.set_rules(busy_light_rules);
+    }
+
     if conf.version < GEN_CONF_VERSION_CUTOFF {
         fs::copy(
             INI_FILE_NAME,
@@ -147,6 +279,12 @@ fn create_configuration() -> Configuration {
         ha: create_ha_configuration(),
         teams: create_teams_configuration(),
         mqtt: create_mqtt_configuration(),
+        http_api: create_http_api_configuration(),
+        teams_log: create_teams_log_configuration(),
+        presence: create_presence_configuration(),
+        busy_light: create_busy_light_configuration(),
+        webhooks: Vec::new(),
+        instance_name: "".to_string(),
         version: 0,
     }
 }
@@ -156,7 +294,15 @@ fn add_entity(ini: &mut Ini, section: &str, ha_entity: &HaEntity) {
         .set(HA_ID, &ha_entity.id)
         .set(HA_FRIENDLY_NAME, &ha_entity.friendly_name)
         .set(HA_ICON_ON, &ha_entity.icons.on)
-        .set(HA_ICON_OFF, &ha_entity.icons.off);
+        .set(HA_ICON_OFF, &ha_entity.icons.off)
+        .set(HA_STATE_ON, &ha_entity.states.on)
+        .set(HA_STATE_OFF, &ha_entity.states.off)
+        .set(HA_DEVICE_CLASS, &ha_entity.device_class);
+
+    for (name, value) in &ha_entity.attributes {
+        ini.with_section(Some(section))
+            .set(format!("{}{}", HA_ATTRIBUTE, name), value);
+    }
 }
 fn save_ha_configuration(conf: &Configuration) {
     let mut ini = Ini::new();
@@ -166,45 +312,77 @@ fn save_ha_configuration(conf: &Configuration) {
 
     ini.with_section(Some(HOME_ASSISTANT))
         .set(HA_URL, &conf.ha.url)
-        .set(HA_LONG_LIVE_TOKEN, encrypt(&conf.ha.long_live_token));
-
-    let ha_entities = &conf.ha.entities;
-    add_entity(&mut ini, HA_MUTED, &ha_entities.is_muted);
-    add_entity(&mut ini, HA_VIDEO_ON, &ha_entities.is_video_on);
-    add_entity(&mut ini, HA_HAND_RAISED, &ha_entities.is_hand_raised);
-    add_entity(&mut ini, HA_IN_A_MEETING, &ha_entities.is_in_meeting);
-    add_entity(&mut ini, HA_RECORDING, &ha_entities.is_recording_on);
-    add_entity(
-        &mut ini,
-        HA_BACKGROUND_BLURRED,
-        &ha_entities.is_background_blurred,
-    );
-    add_entity(&mut ini, HA_SHARING, &ha_entities.is_sharing);
-    add_entity(
-        &mut ini,
-        HA_UNREAD_MESSAGES,
-        &ha_entities.has_unread_messages,
-    );
+        .set(HA_LONG_LIVE_TOKEN, encrypt(&conf.ha.long_live_token))
+        .set(HA_MODE, conf.ha.mode.to_string());
+
+    for key in TeamsStateKey::ALL {
+        add_entity(&mut ini, ha_section(key), conf.ha.entities.get(key));
+    }
 
     let mqtt = &conf.mqtt;
     ini.with_section(Some(MQTT))
         .set(MQTT_URL, mqtt.url())
         .set(MQTT_PORT, &mqtt.port.to_string())
         .set(MQTT_TOPIC, &mqtt.topic)
+        .set(MQTT_COMMAND_TOPIC, &mqtt.command_topic)
+        .set(MQTT_DISCOVERY, mqtt.discovery.to_string())
+        .set(MQTT_DISCOVERY_PREFIX, &mqtt.discovery_prefix)
+        .set(MQTT_NODE_ID, &mqtt.node_id)
         .set(MQTT_USERNAME, &mqtt.username)
         .set(MQTT_PASSWORD, encrypt(&mqtt.password));
 
-    let mqtt_entities = &conf.mqtt.mqtt_entities;
-    ini.with_section(Some(MQTT_ENTITIES))
-        .set(MQTT_MUTED, &mqtt_entities.muted)
-        .set(MQTT_VIDEO, &mqtt_entities.video)
-        .set(MQTT_HAND_RAISED, &mqtt_entities.hand_raised)
-        .set(MQTT_MEETING, &mqtt_entities.meeting)
-        .set(MQTT_RECORDING, &mqtt_entities.recording)
-        .set(MQTT_BACKGROUND_BLURRED, &mqtt_entities.background_blurred)
-        .set(MQTT_SHARING, &mqtt_entities.sharing)
-        .set(MQTT_UNREAD_MESSAGES, &mqtt_entities.unread_messages);
+    for key in TeamsStateKey::ALL {
+        ini.with_section(Some(MQTT_ENTITIES)).set(
+            mqtt_entity_config_name(key),
+            conf.mqtt.mqtt_entities.get(key),
+        );
+    }
+
+    let http_api = &conf.http_api;
+    ini.with_section(Some(HTTP_API))
+        .set(HTTP_API_ENABLED, http_api.enabled.to_string())
+        .set(HTTP_API_PORT, http_api.port.to_string())
+        .set(HTTP_API_BEARER_TOKEN, encrypt(&http_api.bearer_token));
+
+    ini.with_section(Some(TEAMS_LOG))
+        .set(TEAMS_LOG_ENABLED, conf.teams_log.enabled.to_string())
+        .set(TEAMS_LOG_PATH, &conf.teams_log.path);
+
+    ini.with_section(Some(PRESENCE))
+        .set(PRESENCE_PRECEDENCE, conf.presence.precedence.to_string());
+
+    ini.with_section(Some(BUSY_LIGHT))
+        .set(BUSY_LIGHT_ENABLED, conf.busy_light.enabled.to_string());
+    add_entity(&mut ini, BUSY_LIGHT, &conf.busy_light.ha_entity);
+
+    let device = &conf.busy_light.device;
+    ini.with_section(Some(BUSY_LIGHT_DEVICE))
+        .set(BUSY_LIGHT_DEVICE_DRIVER, &device.driver)
+        .set(BUSY_LIGHT_DEVICE_PORT, &device.port)
+        .set(BUSY_LIGHT_DEVICE_BAUD_RATE, device.baud_rate.to_string());
+
+    for rule in &conf.busy_light.rule_values {
+        ini.with_section(Some(format!("{}{}", BUSY_LIGHT_RULE, rule.name)))
+            .set(BUSY_LIGHT_WHEN, &rule.when)
+            .set(BUSY_LIGHT_COLOR, &rule.color);
+    }
+
+    for webhook in &conf.webhooks {
+        let section = format!("{}{}", WEBHOOK, webhook.name);
+        ini.with_section(Some(section.as_str()))
+            .set(WEBHOOK_URL, &webhook.url)
+            .set(WEBHOOK_METHOD, &webhook.method)
+            .set(WEBHOOK_HMAC_SECRET, encrypt(&webhook.hmac_secret))
+            .set(WEBHOOK_BODY_TEMPLATE, &webhook.body_template);
+
+        for (name, value) in &webhook.headers {
+            ini.with_section(Some(section.as_str()))
+                .set(format!("{}{}", WEBHOOK_HEADER, name), value);
+        }
+    }
+
     ini.with_section(Some(GENERAL))
-        .set(GEN_CONF_VERSION, GEN_CONF_VERSION_CURRENT.to_string());
+        .set(GEN_CONF_VERSION, GEN_CONF_VERSION_CURRENT.to_string())
+        .set(GEN_INSTANCE_NAME, &conf.instance_name);
     ini.write_to_file(INI_FILE_NAME).unwrap();
 }
@@ -1,52 +1,397 @@
-use crate::home_assistant::configuration::{HaConfiguration, HaEntity};
-use crate::teams_ws::states::TeamsStates;
+use crate::busy_light::configuration::BusyLightConfiguration;
+use crate::home_assistant::attributes::{render_attribute, AttributeValues, MeetingClock};
+use crate::home_assistant::cache::HaCache;
+use crate::home_assistant::configuration::{HaConfiguration, HaEntity, HaMode};
+use crate::home_assistant::websocket::{websocket_url, HaEvent, HaWebsocket};
+use crate::mqtt::api::{create_identity_payload, create_state_payload};
+use crate::mqtt::configuration::{
+    create_mqtt_configuration, MqttConfiguration, MQTT_AVAILABILITY_OFFLINE, MQTT_AVAILABILITY_ONLINE,
+};
+use crate::mqtt::discovery::{create_discovery_messages, DiscoveryMessage};
+use crate::teams_ws::actions::{ActionSender, TeamsAction};
+use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
 use crate::traits::Listener;
-use crate::utils::bool_to_str;
 use anyhow::anyhow;
 use async_trait::async_trait;
+use chrono::Utc;
 use home_assistant_rest::post::StateParams;
 use home_assistant_rest::Client;
 use log::{error, info};
+use serde_json::json;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 use futures_util::future::try_join_all;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::sync::{watch, RwLock};
+
+// the states posted through the REST API are wiped when HA restarts
+const EVENT_HOMEASSISTANT_STARTED: &str = "homeassistant_started";
+// fired from HA automations or scripts, e.g. `event: teams_status_command` with `event_data: {action: leave-call}`
+const EVENT_COMMAND: &str = "teams_status_command";
+const EVENT_COMMAND_ACTION: &str = "action";
+// optional, the command is for every instance without it
+const EVENT_COMMAND_INSTANCE: &str = "instance";
+const EVENT_ACTION_FAILED: &str = "teams_status_action_failed";
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+const CACHE_FILE_NAME: &str = "ha_state_cache.json";
+// prefixed with the instance like the other entities
+const IDENTITY_ENTITY_ID: &str = "sensor.teams_status_identity";
+const IDENTITY_FRIENDLY_NAME: &str = "Teams Status Identity";
+const IDENTITY_ICON: &str = "mdi:card-account-details";
 
 pub struct HaApi {
-    ha_configuration: HaConfiguration,
+    // an invalid URL is only reported by the updates, so it shows up with the other connection errors
+    publisher: anyhow::Result<Arc<HaPublisher>>,
 }
 
 impl HaApi {
-    pub fn new(ha_configuration: HaConfiguration) -> anyhow::Result<Self> {
-        Ok(Self {
+    pub fn new(
+        ha_configuration: HaConfiguration,
+        busy_light: Option<Arc<BusyLightConfiguration>>,
+        action_sender: ActionSender,
+    ) -> Self {
+        let publisher = websocket_url(&ha_configuration.url).map(|url| {
+            let (event_sender, event_receiver) = unbounded_channel();
+            let websocket = HaWebsocket::spawn(
+                url,
+                ha_configuration.long_live_token.clone(),
+                vec![EVENT_HOMEASSISTANT_STARTED.to_string(), EVENT_COMMAND.to_string()],
+                event_sender,
+            );
+            let is_connected = websocket.subscribe_connected();
+            let publisher = Arc::new(HaPublisher::new(ha_configuration, busy_light, websocket));
+
+            tokio::spawn(handle_events(
+                publisher.clone(),
+                action_sender,
+                event_receiver,
+                is_connected,
+            ));
+
+            publisher
+        });
+
+        Self { publisher }
+    }
+
+    fn publisher(&self) -> anyhow::Result<&HaPublisher> {
+        self.publisher.as_deref().map_err(|error| anyhow!("{:#}", error))
+    }
+}
+
+async fn handle_events(
+    publisher: Arc<HaPublisher>,
+    action_sender: ActionSender,
+    mut event_receiver: UnboundedReceiver<HaEvent>,
+    mut is_connected: watch::Receiver<bool>,
+) {
+    loop {
+        tokio::select! {
+            changed = is_connected.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+
+                // the entities may be missing after the application, HA or the connection went down
+                if *is_connected.borrow_and_update() {
+                    publisher.restore().await;
+                }
+            }
+            event = event_receiver.recv() => {
+                let Some(event) = event else { break };
+
+                match event.event_type.as_str() {
+                    EVENT_HOMEASSISTANT_STARTED => {
+                        info!("Home Assistant restarted, sending the states again");
+                        publisher.restore().await;
+                    }
+                    EVENT_COMMAND if !publisher.is_command_target(&event) => {
+                        info!("Ignoring Home Assistant command for another instance: {}", event.data);
+                    }
+                    EVENT_COMMAND => match action_from_event(&event) {
+                        Ok(action) => {
+                            info!("Home Assistant requested {}", action);
+
+                            if let Err(error) = action_sender.send(action) {
+                                error!("Unable to forward {} to Teams: {}", action, error);
+                            }
+                        }
+                        Err(error) => error!("Ignoring Home Assistant command {}: {}", event.data, error),
+                    },
+                    event_type => info!("Ignoring Home Assistant event {}: {}", event_type, event.data),
+                }
+            }
+        }
+    }
+}
+
+/// Takes the same action names as the MQTT command topic, e.g. `toggle-mute` or `send-reaction:like`
+fn action_from_event(event: &HaEvent) -> anyhow::Result<TeamsAction> {
+    event.data[EVENT_COMMAND_ACTION]
+        .as_str()
+        .ok_or_else(|| anyhow!("'{}' is missing", EVENT_COMMAND_ACTION))?
+        .parse()
+}
+
+/// Sends the states in the configured mode, and keeps them in the cache. The states mode uses the REST API, as the
+/// websocket API has no command to set the state of an entity.
+struct HaPublisher {
+    ha_configuration: HaConfiguration,
+    busy_light: Option<Arc<BusyLightConfiguration>>,
+    websocket: HaWebsocket,
+    // created once and reused by every update
+    client: OnceLock<Client>,
+    // the discovery mode publishes the same topics and payloads as the MQTT integration with its default configuration
+    mqtt_configuration: MqttConfiguration,
+    discovery_messages: Vec<DiscoveryMessage>,
+    cache: Mutex<HaCache>,
+    // held by the updates while they post their states, and exclusively by the restore, so a cached state from the
+    // previous run cannot be posted after a newer one
+    restore_lock: RwLock<()>,
+    // for the attribute placeholders
+    meeting_clock: Mutex<MeetingClock>,
+}
+
+impl HaPublisher {
+    fn new(
+        ha_configuration: HaConfiguration,
+        busy_light: Option<Arc<BusyLightConfiguration>>,
+        websocket: HaWebsocket,
+    ) -> Self {
+        let mut mqtt_configuration = create_mqtt_configuration();
+        mqtt_configuration.set_instance(ha_configuration.instance.clone());
+        let mut cache = HaCache::load(PathBuf::from(CACHE_FILE_NAME));
+
+        // the identity is cached, so it is sent along with the restored states on every connection
+        let discovery_messages = match ha_configuration.mode {
+            HaMode::States => {
+                let instance = &ha_configuration.instance;
+                let mut attributes: HashMap<String, String> = instance.identity().into_iter().collect();
+                attributes.insert("friendly_name".to_string(), instance.prefix_ha_name(IDENTITY_FRIENDLY_NAME));
+                attributes.insert("icon".to_string(), IDENTITY_ICON.to_string());
+                cache.set_state(&instance.prefix_entity_id(IDENTITY_ENTITY_ID), instance.label(), &attributes);
+                Vec::new()
+            }
+            HaMode::Discovery => {
+                // Teams is not connected yet, the cached availability is from the previous run
+                cache.set_topic(&mqtt_configuration.availability_topic(), MQTT_AVAILABILITY_OFFLINE);
+                cache.set_topic(&mqtt_configuration.identity_topic(), &create_identity_payload(&mqtt_configuration));
+                create_discovery_messages(&mqtt_configuration, busy_light.as_deref())
+            }
+        };
+
+        Self {
             ha_configuration,
-        })
+            busy_light,
+            websocket,
+            client: OnceLock::new(),
+            mqtt_configuration,
+            discovery_messages,
+            cache: Mutex::new(cache),
+            restore_lock: RwLock::new(()),
+            meeting_clock: Mutex::new(MeetingClock::default()),
+        }
     }
 
-    // friendly_name is needed as API calls wipe the configured name
-    async fn update_ha(&self, state: &AtomicBool, prev_state: &AtomicBool, ha_entity: &HaEntity, force_update: bool) -> anyhow::Result<()> {
-        let state_bool = state.load(Ordering::Relaxed);
-        let prev_state_bool = prev_state.load(Ordering::Relaxed);
+    fn is_command_target(&self, event: &HaEvent) -> bool {
+        self.ha_configuration
+            .instance
+            .is_target(event.data[EVENT_COMMAND_INSTANCE].as_str())
+    }
 
-        // we exit early if nothing has changed, and we are not forcing an update
-        if state_bool == prev_state_bool && !force_update {
-            return Ok(());
+    fn client(&self) -> anyhow::Result<&Client> {
+        if let Some(client) = self.client.get() {
+            return Ok(client);
         }
 
         let client = Client::new(&*self.ha_configuration.url, &*self.ha_configuration.long_live_token)?;
-        let api_status = client.get_api_status().await;
+        Ok(self.client.get_or_init(|| client))
+    }
 
-        if api_status.is_err() || api_status.unwrap().message != "API running." {
-            error!("Home Assistant API cannot be reached");
-            return Err(anyhow!("Home Assistant API cannot be reached"));
+    async fn publish(&self, snapshot: &TeamsSnapshot, changed: &[TeamsStateKey]) -> anyhow::Result<()> {
+        if self.ha_configuration.mode == HaMode::Discovery {
+            // the whole state is published every time, as it is retained like with the MQTT integration
+            let payload = create_state_payload(&self.mqtt_configuration, snapshot);
+            self.publish_mqtt(&self.mqtt_configuration.topic, &payload)?;
+
+            if let Some(busy_light) = &self.busy_light {
+                let payload = busy_light.state(snapshot).to_json().to_string();
+                self.publish_mqtt(&self.mqtt_configuration.busy_light_topic(), &payload)?;
+            }
+
+            return Ok(());
+        }
+
+        let _guard = self.restore_lock.read().await;
+        let now = Utc::now();
+        let values = AttributeValues {
+            host_name: self.ha_configuration.instance.host_name.clone(),
+            meeting_duration: self
+                .meeting_clock
+                .lock()
+                .unwrap()
+                .update(snapshot.get(TeamsStateKey::IsInMeeting), now),
+            timestamp: now,
+        };
+        let futures = changed.iter().map(|key| self.update_ha(snapshot, *key, &values));
+
+        try_join_all(futures).await?;
+
+        // any state can change the light, it is updated along with them
+        if let Some(busy_light) = &self.busy_light {
+            self.update_ha_busy_light(snapshot, busy_light, &values).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn publish_availability(&self, is_available: bool) -> anyhow::Result<()> {
+        if self.ha_configuration.mode == HaMode::States {
+            // entities posted through the REST API have no availability
+            return Ok(());
+        }
+
+        let availability = if is_available {
+            MQTT_AVAILABILITY_ONLINE
+        } else {
+            MQTT_AVAILABILITY_OFFLINE
+        };
+
+        self.publish_mqtt(&self.mqtt_configuration.availability_topic(), availability)
+    }
+
+    /// Sends the cached states again, along with the discovery configs in the discovery mode
+    async fn restore(&self) {
+        let result = match self.ha_configuration.mode {
+            HaMode::States => self.restore_states().await,
+            HaMode::Discovery => self.restore_topics(),
+        };
+
+        if let Err(error) = result {
+            error!("Unable to restore the Home Assistant states: {}", error);
+        }
+    }
+
+    async fn restore_states(&self) -> anyhow::Result<()> {
+        let _guard = self.restore_lock.write().await;
+        // read once the updates are done, so it holds their states
+        let states = self.cache.lock().unwrap().states();
+
+        for (entity_id, state, attributes) in states {
+            self.send_state(entity_id, state, attributes).await?;
+        }
+
+        Ok(())
+    }
+
+    // the cache stays locked while sending, so a newer payload cannot be sent before the cached one
+    fn restore_topics(&self) -> anyhow::Result<()> {
+        let cache = self.cache.lock().unwrap();
+
+        for message in &self.discovery_messages {
+            self.websocket.send_command(mqtt_publish_command(&message.topic, &message.payload))?;
+        }
+
+        for (topic, payload) in cache.topics() {
+            self.websocket.send_command(mqtt_publish_command(&topic, &payload))?;
+        }
+
+        Ok(())
+    }
+
+    fn publish_mqtt(&self, topic: &str, payload: &str) -> anyhow::Result<()> {
+        let mut cache = self.cache.lock().unwrap();
+        self.websocket.send_command(mqtt_publish_command(topic, payload))?;
+        cache.set_topic(topic, payload);
+
+        Ok(())
+    }
+
+    async fn send_state(
+        &self,
+        entity_id: String,
+        state: String,
+        attributes: HashMap<String, String>,
+    ) -> anyhow::Result<()> {
+        let params = StateParams {
+            entity_id: entity_id.clone(),
+            state: state.clone(),
+            attributes: attributes.clone(),
+        };
+
+        self.client()?
+            .post_states(params)
+            .await
+            .map_err(|error| anyhow!("{}", error))?;
+        self.cache.lock().unwrap().set_state(&entity_id, &state, &attributes);
+
+        Ok(())
+    }
+
+    async fn update_ha(
+        &self,
+        snapshot: &TeamsSnapshot,
+        key: TeamsStateKey,
+        values: &AttributeValues,
+    ) -> anyhow::Result<()> {
+        let ha_entity = self.ha_configuration.entities.get(key);
+        let is_on = snapshot.get(key);
+
+        let state_str = match (key.is_binary(), is_on) {
+            (true, true) => ha_entity.states.on.clone(),
+            (true, false) => ha_entity.states.off.clone(),
+            (false, _) => snapshot.state_str(key),
+        };
+
+        self.post_state(ha_entity, is_on, state_str, HashMap::new(), values)
+            .await
+    }
+
+    async fn update_ha_busy_light(
+        &self,
+        snapshot: &TeamsSnapshot,
+        busy_light: &BusyLightConfiguration,
+        values: &AttributeValues,
+    ) -> anyhow::Result<()> {
+        let state = busy_light.state(snapshot);
+        let color = state.color;
+
+        // the attributes can only be strings through the REST client, HA templates still read this as a list
+        let mut attributes = HashMap::new();
+        attributes.insert(
+            "rgb_color".to_string(),
+            format!("[{}, {}, {}]", color.red, color.green, color.blue),
+        );
+
+        self.post_state(&busy_light.ha_entity, !color.is_off(), state.name, attributes, values)
+            .await
+    }
+
+    // friendly_name is needed as API calls wipe the configured name
+    async fn post_state(
+        &self,
+        ha_entity: &HaEntity,
+        is_on: bool,
+        state_str: String,
+        mut attributes: HashMap<String, String>,
+        values: &AttributeValues,
+    ) -> anyhow::Result<()> {
+        for (name, value) in &ha_entity.attributes {
+            attributes.insert(name.to_string(), render_attribute(value, values));
+        }
+
+        if !ha_entity.device_class.is_empty() {
+            attributes.insert("device_class".to_string(), ha_entity.device_class.to_string());
         }
 
-        let mut attributes: HashMap<String, String> = HashMap::new();
         attributes.insert(
             "friendly_name".to_string(),
             ha_entity.friendly_name.to_string(),
         );
 
-        let icon = if state_bool {
+        let icon = if is_on {
             &ha_entity.icons.on
         } else {
             &ha_entity.icons.off
@@ -54,95 +399,96 @@ impl HaApi {
 
         attributes.insert("icon".to_string(), icon.to_string());
 
-        let state_str = bool_to_str(state_bool);
-        let params = StateParams {
-            entity_id: ha_entity.id.to_string(),
-            state: state_str.clone(),
-            attributes,
-        };
-
         info!("Updating HA entity ({}) to '{}'", &ha_entity.id, &state_str);
 
-        let post_states_res = client.post_states(params).await;
-
-        if post_states_res.is_err() {
-            error!("{}", post_states_res.unwrap_err());
-        };
-
-        prev_state.store(state_bool, Ordering::Relaxed);
-
-        Ok(())
+        // returned, so the update is retried like the other listeners
+        self.send_state(ha_entity.id.to_string(), state_str, attributes)
+            .await
+            .map_err(|error| anyhow!("Unable to update HA entity ({}): {}", ha_entity.id, error))
     }
 }
 
+// retained, so HA gets them back from the broker after a restart
+fn mqtt_publish_command(topic: &str, payload: &str) -> serde_json::Value {
+    json!({
+        "type": "call_service",
+        "domain": "mqtt",
+        "service": "publish",
+        "service_data": {"topic": topic, "payload": payload, "qos": 1, "retain": true},
+    })
+}
+
 #[async_trait]
 impl Listener for HaApi {
-    async fn notify_changed(&self, teams_states: &TeamsStates, force_update: bool) -> anyhow::Result<()> {
-        // Reflection would be nice here... Tried with bevy_reflect but ran into an issue with AtomicBool
-        let mut futures = Vec::new();
-
-        futures.push(self.update_ha(
-            &teams_states.is_in_meeting,
-            &teams_states.prev_is_in_meeting,
-            &self.ha_configuration.entities.is_in_meeting,
-            force_update,
-        ));
-
-        futures.push(self.update_ha(
-            &teams_states.is_video_on,
-            &teams_states.prev_is_video_on,
-            &self.ha_configuration.entities.is_video_on,
-            force_update,
-        ));
-
-        futures.push(self.update_ha(
-            &teams_states.is_muted,
-            &teams_states.prev_is_muted,
-            &self.ha_configuration.entities.is_muted,
-            force_update,
-        ));
-
-        futures.push(self.update_ha(
-            &teams_states.is_hand_raised,
-            &teams_states.prev_is_hand_raised,
-            &self.ha_configuration.entities.is_hand_raised,
-            force_update,
-        ));
-
-        futures.push(self.update_ha(
-            &teams_states.is_recording_on,
-            &teams_states.prev_is_recording_on,
-            &self.ha_configuration.entities.is_recording_on,
-            force_update,
-        ));
-
-        futures.push(self.update_ha(
-            &teams_states.is_background_blurred,
-            &teams_states.prev_is_background_blurred,
-            &self.ha_configuration.entities.is_background_blurred,
-            force_update,
-        ));
-
-        futures.push(self.update_ha(
-            &teams_states.is_sharing,
-            &teams_states.prev_is_sharing,
-            &self.ha_configuration.entities.is_sharing,
-            force_update,
-        ));
-
-        futures.push(self.update_ha(
-            &teams_states.has_unread_messages,
-            &teams_states.prev_has_unread_messages,
-            &self.ha_configuration.entities.has_unread_messages,
-            force_update,
-        ));
+    fn name(&self) -> &'static str {
+        "Home Assistant"
+    }
 
-        try_join_all(futures).await?;
+    // checked once per update through the websocket, rather than asking the REST API before each entity
+    async fn notify_changed(&self, snapshot: &TeamsSnapshot, changed: &[TeamsStateKey]) -> anyhow::Result<()> {
+        let publisher = self.publisher()?;
 
-        Ok(())
+        if !publisher.websocket.wait_connected(CONNECTION_TIMEOUT).await {
+            error!("Home Assistant API cannot be reached");
+            return Err(anyhow!("Home Assistant API cannot be reached"));
+        }
+
+        publisher.publish(snapshot, changed).await
+    }
+
+    // fired as an event, so automations can react when Teams refuses their command
+    async fn notify_action_failed(&self, action: &TeamsAction, reason: &str) -> anyhow::Result<()> {
+        let publisher = self.publisher()?;
+
+        publisher.websocket.send_command(json!({
+            "type": "fire_event",
+            "event_type": EVENT_ACTION_FAILED,
+            "event_data": {
+                "action": action.to_string(),
+                "reason": reason,
+                EVENT_COMMAND_INSTANCE: publisher.ha_configuration.instance.id,
+            },
+        }))
+    }
+
+    async fn notify_teams_connected(&self, is_connected: bool) -> anyhow::Result<()> {
+        self.publisher()?.publish_availability(is_connected).await
     }
 
     fn reconnect(&mut self) {
-        // considered not needed for now, as I believe the API will reconnect upon failure (not tested)
+        // the websocket reconnects on its own, the REST client holds no connection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::home_assistant::api::action_from_event;
+    use crate::home_assistant::websocket::HaEvent;
+    use crate::teams_ws::actions::{TeamsAction, TeamsReaction};
+    use serde_json::json;
+
+    fn command_event(data: serde_json::Value) -> HaEvent {
+        HaEvent {
+            event_type: "teams_status_command".to_string(),
+            data,
+        }
+    }
+
+    #[test]
+    fn action_from_event_action_name_will_parse() {
+        let event = command_event(json!({"action": "leave-call"}));
+        assert_eq!(action_from_event(&event).unwrap(), TeamsAction::LeaveCall);
+
+        let event = command_event(json!({"action": "send-reaction:wow"}));
+        assert_eq!(
+            action_from_event(&event).unwrap(),
+            TeamsAction::SendReaction(TeamsReaction::Wow)
+        );
+    }
+
+    #[test]
+    fn action_from_event_missing_or_unknown_action_will_fail() {
+        assert!(action_from_event(&command_event(json!({}))).is_err());
+        assert!(action_from_event(&command_event(json!({"action": "dance"}))).is_err());
     }
 }
@@ -0,0 +1,72 @@
+use chrono::{DateTime, Utc};
+
+const PLACEHOLDER_HOST_NAME: &str = "{{host_name}}";
+const PLACEHOLDER_MEETING_DURATION: &str = "{{meeting_duration}}";
+const PLACEHOLDER_TIMESTAMP: &str = "{{timestamp}}";
+
+/// Values of the placeholders, the same for every entity of an update
+pub struct AttributeValues {
+    pub host_name: String,
+    // in seconds, 0 when not in a meeting
+    pub meeting_duration: i64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Replaces `{{host_name}}`, `{{meeting_duration}}` and `{{timestamp}}` in a configured attribute
+pub fn render_attribute(value: &str, values: &AttributeValues) -> String {
+    value
+        .replace(PLACEHOLDER_HOST_NAME, &values.host_name)
+        .replace(PLACEHOLDER_MEETING_DURATION, &values.meeting_duration.to_string())
+        .replace(PLACEHOLDER_TIMESTAMP, &values.timestamp.to_rfc3339())
+}
+
+/// Tracks when the current meeting started
+#[derive(Default)]
+pub struct MeetingClock {
+    started: Option<DateTime<Utc>>,
+}
+
+impl MeetingClock {
+    /// Returns the duration of the meeting in seconds
+    pub fn update(&mut self, is_in_meeting: bool, now: DateTime<Utc>) -> i64 {
+        if !is_in_meeting {
+            self.started = None;
+            return 0;
+        }
+
+        let started = *self.started.get_or_insert(now);
+        (now - started).num_seconds()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::home_assistant::attributes::{render_attribute, AttributeValues, MeetingClock};
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn render_attribute_placeholders_will_be_replaced() {
+        let values = AttributeValues {
+            host_name: "DESKTOP-1".to_string(),
+            meeting_duration: 90,
+            timestamp: Utc.with_ymd_and_hms(2024, 7, 1, 14, 3, 12).unwrap(),
+        };
+
+        assert_eq!(render_attribute("teams-status", &values), "teams-status");
+        assert_eq!(
+            render_attribute("{{host_name}} for {{meeting_duration}}s at {{timestamp}}", &values),
+            "DESKTOP-1 for 90s at 2024-07-01T14:03:12+00:00"
+        );
+    }
+
+    #[test]
+    fn update_meeting_clock_will_restart_with_each_meeting() {
+        let mut meeting_clock = MeetingClock::default();
+        let start = Utc.with_ymd_and_hms(2024, 7, 1, 14, 0, 0).unwrap();
+
+        assert_eq!(meeting_clock.update(true, start), 0);
+        assert_eq!(meeting_clock.update(true, start + Duration::seconds(90)), 90);
+        assert_eq!(meeting_clock.update(false, start + Duration::seconds(120)), 0);
+        assert_eq!(meeting_clock.update(true, start + Duration::seconds(300)), 0);
+    }
+}
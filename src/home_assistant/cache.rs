@@ -0,0 +1,142 @@
+use log::error;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+const STATES: &str = "states";
+const TOPICS: &str = "topics";
+
+/// Last states sent to HA, kept on disk so they can be sent again on startup or when HA restarts, before Teams is
+/// reachable. The states mode keeps the entity states, the discovery mode the MQTT payloads.
+pub struct HaCache {
+    path: PathBuf,
+    // entity id to `{"state": ..., "attributes": {...}}`
+    states: Map<String, Value>,
+    // topic to payload
+    topics: Map<String, Value>,
+}
+
+impl HaCache {
+    /// Starts empty when the file is missing or cannot be read
+    pub fn load(path: PathBuf) -> Self {
+        let cache = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| match serde_json::from_str::<Value>(&content) {
+                Ok(cache) => Some(cache),
+                Err(error) => {
+                    error!("Ignoring the invalid cache {}: {}", path.display(), error);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        let read_section = |name: &str| cache[name].as_object().cloned().unwrap_or_default();
+
+        Self {
+            states: read_section(STATES),
+            topics: read_section(TOPICS),
+            path,
+        }
+    }
+
+    pub fn set_state(&mut self, entity_id: &str, state: &str, attributes: &HashMap<String, String>) {
+        let value = json!({"state": state, "attributes": attributes});
+
+        if insert_if_changed(&mut self.states, entity_id, value) {
+            self.save();
+        }
+    }
+
+    pub fn set_topic(&mut self, topic: &str, payload: &str) {
+        if insert_if_changed(&mut self.topics, topic, json!(payload)) {
+            self.save();
+        }
+    }
+
+    /// (entity id, state, attributes)
+    pub fn states(&self) -> Vec<(String, String, HashMap<String, String>)> {
+        self.states
+            .iter()
+            .map(|(entity_id, value)| {
+                let attributes = value["attributes"]
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                    .collect();
+
+                (
+                    entity_id.clone(),
+                    value["state"].as_str().unwrap_or_default().to_string(),
+                    attributes,
+                )
+            })
+            .collect()
+    }
+
+    /// (topic, payload)
+    pub fn topics(&self) -> Vec<(String, String)> {
+        self.topics
+            .iter()
+            .filter_map(|(topic, payload)| Some((topic.clone(), payload.as_str()?.to_string())))
+            .collect()
+    }
+
+    fn save(&self) {
+        let cache = json!({STATES: self.states, TOPICS: self.topics});
+
+        if let Err(error) = fs::write(&self.path, cache.to_string()) {
+            error!("Unable to write the cache {}: {}", self.path.display(), error);
+        }
+    }
+}
+
+// only written on changes, as most updates only change a few entities
+fn insert_if_changed(section: &mut Map<String, Value>, key: &str, value: Value) -> bool {
+    if section.get(key) == Some(&value) {
+        return false;
+    }
+
+    section.insert(key.to_string(), value);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::home_assistant::cache::HaCache;
+    use std::collections::HashMap;
+    use std::fs;
+
+    #[test]
+    fn load_saved_cache_will_restore_states_and_topics() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("cache.json");
+        let mut cache = HaCache::load(path.clone());
+        let attributes = HashMap::from([("icon".to_string(), "mdi:microphone".to_string())]);
+        cache.set_state("binary_sensor.teams_muted", "on", &attributes);
+        cache.set_topic("teams-status", r#"{"muted":"on"}"#);
+
+        let cache = HaCache::load(path);
+
+        assert_eq!(
+            cache.states(),
+            vec![("binary_sensor.teams_muted".to_string(), "on".to_string(), attributes)]
+        );
+        assert_eq!(
+            cache.topics(),
+            vec![("teams-status".to_string(), r#"{"muted":"on"}"#.to_string())]
+        );
+    }
+
+    #[test]
+    fn load_invalid_file_will_be_empty() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("cache.json");
+        fs::write(&path, "{not json").unwrap();
+
+        let cache = HaCache::load(path);
+
+        assert!(cache.states().is_empty());
+        assert!(cache.topics().is_empty());
+    }
+}
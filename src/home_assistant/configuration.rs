@@ -1,6 +1,14 @@
+use crate::instance::{create_instance, Instance};
+use crate::teams_ws::states::TeamsStateKey;
+use anyhow::anyhow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
 pub const HOME_ASSISTANT: &str = "Home Assistant";
 pub const HA_LONG_LIVE_TOKEN: &str = "Long Live Token";
 pub const HA_URL: &str = "URL";
+pub const HA_MODE: &str = "Mode";
 pub const HA_MUTED: &str = "Home Assistant Entity - Muted";
 pub const HA_VIDEO_ON: &str = "Home Assistant Entity - Video On";
 pub const HA_HAND_RAISED: &str = "Home Assistant Entity - Hand Raised";
@@ -9,114 +17,298 @@ pub const HA_RECORDING: &str = "Home Assistant Entity - Recording";
 pub const HA_BACKGROUND_BLURRED: &str = "Home Assistant Entity - Background Blurred";
 pub const HA_SHARING: &str = "Home Assistant Entity - Sharing";
 pub const HA_UNREAD_MESSAGES: &str = "Home Assistant Entity - Unread Messages";
+pub const HA_CAN_TOGGLE_MUTE: &str = "Home Assistant Entity - Can Toggle Mute";
+pub const HA_CAN_TOGGLE_VIDEO: &str = "Home Assistant Entity - Can Toggle Video";
+pub const HA_CAN_TOGGLE_HAND: &str = "Home Assistant Entity - Can Toggle Hand";
+pub const HA_CAN_TOGGLE_BLUR: &str = "Home Assistant Entity - Can Toggle Blur";
+pub const HA_CAN_LEAVE: &str = "Home Assistant Entity - Can Leave";
+pub const HA_CAN_REACT: &str = "Home Assistant Entity - Can React";
+pub const HA_CAN_TOGGLE_SHARE_TRAY: &str = "Home Assistant Entity - Can Toggle Share Tray";
+pub const HA_CAN_TOGGLE_CHAT: &str = "Home Assistant Entity - Can Toggle Chat";
+pub const HA_CAN_STOP_SHARING: &str = "Home Assistant Entity - Can Stop Sharing";
+pub const HA_CAN_PAIR: &str = "Home Assistant Entity - Can Pair";
+pub const HA_AVAILABILITY: &str = "Home Assistant Entity - Availability";
+pub const HA_PRESENCE: &str = "Home Assistant Entity - Presence";
 pub const HA_ID: &str = "ID";
 pub const HA_FRIENDLY_NAME: &str = "Friendly Name";
 pub const HA_ICON_ON: &str = "Icon On";
 pub const HA_ICON_OFF: &str = "Icon Off";
+pub const HA_STATE_ON: &str = "State On";
+pub const HA_STATE_OFF: &str = "State Off";
+pub const HA_DEVICE_CLASS: &str = "Device Class";
+// followed by the name of the attribute, e.g. `Attribute - last_changed_by`
+pub const HA_ATTRIBUTE: &str = "Attribute - ";
+pub const HA_STATE_ON_DEFAULT: &str = "on";
+pub const HA_STATE_OFF_DEFAULT: &str = "off";
 pub struct HaIcons {
     pub on: String,
     pub off: String,
 }
+// sent instead of on/off for the binary states
+pub struct HaStates {
+    pub on: String,
+    pub off: String,
+}
 pub struct HaEntity {
     pub id: String,
     pub friendly_name: String,
     pub icons: HaIcons,
+    pub states: HaStates,
+    // not sent when empty
+    pub device_class: String,
+    // the placeholders such as `{{host_name}}` are replaced on every update
+    pub attributes: Vec<(String, String)>,
 }
 pub struct HaEntities {
-    pub is_muted: HaEntity,
-    pub is_video_on: HaEntity,
-    pub is_hand_raised: HaEntity,
-    pub is_in_meeting: HaEntity,
-    pub is_recording_on: HaEntity,
-    pub is_background_blurred: HaEntity,
-    pub is_sharing: HaEntity,
-    pub has_unread_messages: HaEntity,
+    entities: BTreeMap<TeamsStateKey, HaEntity>,
+}
+
+impl HaEntities {
+    pub fn get(&self, key: TeamsStateKey) -> &HaEntity {
+        &self.entities[&key]
+    }
+
+    pub fn get_mut(&mut self, key: TeamsStateKey) -> &mut HaEntity {
+        self.entities.get_mut(&key).unwrap()
+    }
+}
+
+/// Name of the ini section holding the entity of a Teams state
+pub fn ha_section(key: TeamsStateKey) -> &'static str {
+    match key {
+        TeamsStateKey::IsMuted => HA_MUTED,
+        TeamsStateKey::IsVideoOn => HA_VIDEO_ON,
+        TeamsStateKey::IsHandRaised => HA_HAND_RAISED,
+        TeamsStateKey::IsInMeeting => HA_IN_A_MEETING,
+        TeamsStateKey::IsRecordingOn => HA_RECORDING,
+        TeamsStateKey::IsBackgroundBlurred => HA_BACKGROUND_BLURRED,
+        TeamsStateKey::IsSharing => HA_SHARING,
+        TeamsStateKey::HasUnreadMessages => HA_UNREAD_MESSAGES,
+        TeamsStateKey::CanToggleMute => HA_CAN_TOGGLE_MUTE,
+        TeamsStateKey::CanToggleVideo => HA_CAN_TOGGLE_VIDEO,
+        TeamsStateKey::CanToggleHand => HA_CAN_TOGGLE_HAND,
+        TeamsStateKey::CanToggleBlur => HA_CAN_TOGGLE_BLUR,
+        TeamsStateKey::CanLeave => HA_CAN_LEAVE,
+        TeamsStateKey::CanReact => HA_CAN_REACT,
+        TeamsStateKey::CanToggleShareTray => HA_CAN_TOGGLE_SHARE_TRAY,
+        TeamsStateKey::CanToggleChat => HA_CAN_TOGGLE_CHAT,
+        TeamsStateKey::CanStopSharing => HA_CAN_STOP_SHARING,
+        TeamsStateKey::CanPair => HA_CAN_PAIR,
+        TeamsStateKey::Availability => HA_AVAILABILITY,
+        TeamsStateKey::Presence => HA_PRESENCE,
+    }
+}
+
+// (id, friendly name, icon on, icon off)
+fn default_entity(key: TeamsStateKey) -> (&'static str, &'static str, &'static str, &'static str) {
+    match key {
+        TeamsStateKey::IsMuted => (
+            "binary_sensor.teams_muted",
+            "Teams Muted",
+            "mdi:microphone",
+            "mdi:microphone-off",
+        ),
+        TeamsStateKey::IsVideoOn => (
+            "binary_sensor.teams_video",
+            "Teams Video",
+            "mdi:webcam",
+            "mdi:webcam-off",
+        ),
+        TeamsStateKey::IsHandRaised => (
+            "binary_sensor.teams_hand_raised",
+            "Teams Hand Raised",
+            "mdi:hand-back-left",
+            "mdi:hand-back-left-off",
+        ),
+        TeamsStateKey::IsInMeeting => (
+            "binary_sensor.teams_meeting",
+            "Teams Meeting",
+            "mdi:phone-in-talk",
+            "mdi:phone-off",
+        ),
+        TeamsStateKey::IsRecordingOn => (
+            "binary_sensor.teams_recording",
+            "Teams Recording",
+            "mdi:record-rec",
+            "mdi:power-off",
+        ),
+        TeamsStateKey::IsBackgroundBlurred => (
+            "binary_sensor.teams_background_blurred",
+            "Teams Background Blurred",
+            "mdi:blur",
+            "mdi:blur-off",
+        ),
+        TeamsStateKey::IsSharing => (
+            "binary_sensor.teams_sharing",
+            "Teams Sharing",
+            "mdi:projector-screen",
+            "mdi:projector-screen-off",
+        ),
+        TeamsStateKey::HasUnreadMessages => (
+            "binary_sensor.teams_unread_messages",
+            "Teams Unread Messages",
+            "mdi:message-alert",
+            "mdi:message-off",
+        ),
+        TeamsStateKey::CanToggleMute => (
+            "binary_sensor.teams_can_toggle_mute",
+            "Teams Can Toggle Mute",
+            "mdi:microphone",
+            "mdi:microphone-off",
+        ),
+        TeamsStateKey::CanToggleVideo => (
+            "binary_sensor.teams_can_toggle_video",
+            "Teams Can Toggle Video",
+            "mdi:webcam",
+            "mdi:webcam-off",
+        ),
+        TeamsStateKey::CanToggleHand => (
+            "binary_sensor.teams_can_toggle_hand",
+            "Teams Can Toggle Hand",
+            "mdi:hand-back-left",
+            "mdi:hand-back-left-off",
+        ),
+        TeamsStateKey::CanToggleBlur => (
+            "binary_sensor.teams_can_toggle_blur",
+            "Teams Can Toggle Blur",
+            "mdi:blur",
+            "mdi:blur-off",
+        ),
+        TeamsStateKey::CanLeave => (
+            "binary_sensor.teams_can_leave",
+            "Teams Can Leave",
+            "mdi:phone-hangup",
+            "mdi:phone-off",
+        ),
+        TeamsStateKey::CanReact => (
+            "binary_sensor.teams_can_react",
+            "Teams Can React",
+            "mdi:emoticon-happy",
+            "mdi:emoticon-neutral",
+        ),
+        TeamsStateKey::CanToggleShareTray => (
+            "binary_sensor.teams_can_toggle_share_tray",
+            "Teams Can Toggle Share Tray",
+            "mdi:projector-screen",
+            "mdi:projector-screen-off",
+        ),
+        TeamsStateKey::CanToggleChat => (
+            "binary_sensor.teams_can_toggle_chat",
+            "Teams Can Toggle Chat",
+            "mdi:chat",
+            "mdi:chat-remove",
+        ),
+        TeamsStateKey::CanStopSharing => (
+            "binary_sensor.teams_can_stop_sharing",
+            "Teams Can Stop Sharing",
+            "mdi:monitor-share",
+            "mdi:monitor-off",
+        ),
+        TeamsStateKey::CanPair => (
+            "binary_sensor.teams_can_pair",
+            "Teams Can Pair",
+            "mdi:link",
+            "mdi:link-off",
+        ),
+        // a sensor rather than a binary_sensor, the off icon is used when offline or unknown
+        TeamsStateKey::Availability => (
+            "sensor.teams_availability",
+            "Teams Availability",
+            "mdi:account-check",
+            "mdi:account-off",
+        ),
+        TeamsStateKey::Presence => (
+            "sensor.teams_presence",
+            "Teams Presence",
+            "mdi:account-clock",
+            "mdi:account-off",
+        ),
+    }
+}
+
+/// How the entities are created in HA
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HaMode {
+    // posted to `/api/states`, they are not in the entity registry and are gone after a restart
+    #[default]
+    States,
+    // MQTT discovery configs published through HA's `mqtt.publish` service, the entities are registered
+    Discovery,
+}
+
+impl FromStr for HaMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "states" => Ok(HaMode::States),
+            "discovery" => Ok(HaMode::Discovery),
+            _ => Err(anyhow!("Unknown mode '{}', expected 'States' or 'Discovery'", value)),
+        }
+    }
+}
+
+impl fmt::Display for HaMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HaMode::States => f.write_str("States"),
+            HaMode::Discovery => f.write_str("Discovery"),
+        }
+    }
 }
 
 pub struct HaConfiguration {
     pub long_live_token: String,
     pub url: String,
+    pub mode: HaMode,
     pub entities: HaEntities,
+    // not saved, it comes from the General section
+    pub instance: Instance,
 }
 
-pub fn create_ha_configuration() -> HaConfiguration {
-    let ha_entities = HaEntities {
-        is_muted: HaEntity {
-            id: "binary_sensor.teams_muted".to_string(),
-            friendly_name: "Teams Muted".to_string(),
-            icons: HaIcons {
-                on: "mdi:microphone".to_string(),
-                off: "mdi:microphone-off".to_string(),
-            },
-        },
-
-        is_video_on: HaEntity {
-            id: "binary_sensor.teams_video".to_string(),
-            friendly_name: "Teams Video".to_string(),
-            icons: HaIcons {
-                on: "mdi:webcam".to_string(),
-                off: "mdi:webcam-off".to_string(),
-            },
-        },
-
-        is_hand_raised: HaEntity {
-            id: "binary_sensor.teams_hand_raised".to_string(),
-            friendly_name: "Teams Hand Raised".to_string(),
-            icons: HaIcons {
-                on: "mdi:hand-back-left".to_string(),
-                off: "mdi:hand-back-left-off".to_string(),
-            },
-        },
+impl HaConfiguration {
+    /// Prefixes the entities, only to be called once as the prefix is not saved
+    pub fn set_instance(&mut self, instance: Instance) {
+        for key in TeamsStateKey::ALL {
+            instance.prefix_ha_entity(self.entities.get_mut(key));
+        }
 
-        is_in_meeting: HaEntity {
-            id: "binary_sensor.teams_meeting".to_string(),
-            friendly_name: "Teams Meeting".to_string(),
-            icons: HaIcons {
-                on: "mdi:phone-in-talk".to_string(),
-                off: "mdi:phone-off".to_string(),
-            },
-        },
+        self.instance = instance;
+    }
+}
 
-        is_recording_on: HaEntity {
-            id: "binary_sensor.teams_recording".to_string(),
-            friendly_name: "Teams Recording".to_string(),
-            icons: HaIcons {
-                on: "mdi:record-rec".to_string(),
-                off: "mdi:power-off".to_string(),
-            },
+pub fn create_ha_entity(id: &str, friendly_name: &str, icon_on: &str, icon_off: &str) -> HaEntity {
+    HaEntity {
+        id: id.to_string(),
+        friendly_name: friendly_name.to_string(),
+        icons: HaIcons {
+            on: icon_on.to_string(),
+            off: icon_off.to_string(),
         },
-
-        is_background_blurred: HaEntity {
-            id: "binary_sensor.teams_background_blurred".to_string(),
-            friendly_name: "Teams Background Blurred".to_string(),
-            icons: HaIcons {
-                on: "mdi:blur".to_string(),
-                off: "mdi:blur-off".to_string(),
-            },
+        states: HaStates {
+            on: HA_STATE_ON_DEFAULT.to_string(),
+            off: HA_STATE_OFF_DEFAULT.to_string(),
         },
+        device_class: "".to_string(),
+        attributes: Vec::new(),
+    }
+}
 
-        is_sharing: HaEntity {
-            id: "binary_sensor.teams_sharing".to_string(),
-            friendly_name: "Teams Sharing".to_string(),
-            icons: HaIcons {
-                on: "mdi:projector-screen".to_string(),
-                off: "mdi:projector-screen-off".to_string(),
-            },
-        },
+pub fn create_ha_configuration() -> HaConfiguration {
+    let entities = TeamsStateKey::ALL
+        .into_iter()
+        .map(|key| {
+            let (id, friendly_name, icon_on, icon_off) = default_entity(key);
 
-        has_unread_messages: HaEntity {
-            id: "binary_sensor.teams_unread_messages".to_string(),
-            friendly_name: "Teams Unread Messages".to_string(),
-            icons: HaIcons {
-                on: "mdi:message-alert".to_string(),
-                off: "mdi:message-off".to_string(),
-            },
-        },
-    };
+            (key, create_ha_entity(id, friendly_name, icon_on, icon_off))
+        })
+        .collect();
 
     HaConfiguration {
         long_live_token: "".to_string(),
         url: "".to_string(),
-        entities: ha_entities,
+        mode: HaMode::default(),
+        entities: HaEntities { entities },
+        instance: create_instance(""),
     }
 }
@@ -1,2 +1,5 @@
 pub mod api;
+mod attributes;
+mod cache;
 pub mod configuration;
+mod websocket;
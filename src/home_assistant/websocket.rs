@@ -0,0 +1,365 @@
+use anyhow::{anyhow, Context};
+use futures_util::{SinkExt, StreamExt};
+use log::{error, info};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use url::Url;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// HA is considered unreachable when it does not answer a ping before the next one
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+type HaStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug)]
+pub struct HaEvent {
+    pub event_type: String,
+    pub data: Value,
+}
+
+/// `http://homeassistant.local:8123` becomes `ws://homeassistant.local:8123/api/websocket`
+pub fn websocket_url(ha_url: &str) -> anyhow::Result<Url> {
+    let mut url = Url::parse(ha_url).with_context(|| format!("Invalid Home Assistant URL '{}'", ha_url))?;
+    let scheme = match url.scheme() {
+        "http" | "ws" => "ws",
+        "https" | "wss" => "wss",
+        scheme => return Err(anyhow!("Unsupported Home Assistant URL scheme '{}'", scheme)),
+    };
+
+    url.set_scheme(scheme)
+        .map_err(|_| anyhow!("Invalid Home Assistant URL '{}'", ha_url))?;
+    let path = format!("{}/api/websocket", url.path().trim_end_matches('/'));
+    url.set_path(&path);
+
+    Ok(url)
+}
+
+/// Persistent connection to the Home Assistant websocket API, authenticated once and reconnected with backoff.
+/// It tells whether HA is reachable, forwards the subscribed events and sends commands.
+pub struct HaWebsocket {
+    is_connected: watch::Receiver<bool>,
+    command_sender: UnboundedSender<Value>,
+    task: JoinHandle<()>,
+}
+
+impl HaWebsocket {
+    pub fn spawn(
+        url: Url,
+        token: String,
+        event_types: Vec<String>,
+        event_sender: UnboundedSender<HaEvent>,
+    ) -> Self {
+        let (connected_sender, is_connected) = watch::channel(false);
+        let (command_sender, mut command_receiver) = unbounded_channel();
+        let task = tokio::spawn(async move {
+            let mut backoff = INITIAL_BACKOFF;
+
+            loop {
+                let result = run_connection(
+                    &url,
+                    &token,
+                    &event_types,
+                    &connected_sender,
+                    &event_sender,
+                    &mut command_receiver,
+                )
+                .await;
+                let was_connected = connected_sender.send_replace(false);
+
+                if was_connected {
+                    backoff = INITIAL_BACKOFF;
+                }
+
+                if let Err(error) = result {
+                    error!(
+                        "Home Assistant websocket: {:#}: Reconnecting in {} seconds...",
+                        error,
+                        backoff.as_secs()
+                    );
+                }
+
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        });
+
+        Self {
+            is_connected,
+            command_sender,
+            task,
+        }
+    }
+
+    /// Changes to true on every connection, once authenticated
+    pub fn subscribe_connected(&self) -> watch::Receiver<bool> {
+        self.is_connected.clone()
+    }
+
+    /// Sends a command such as `fire_event`, without its id, it fails rather than waiting while disconnected
+    pub fn send_command(&self, command: Value) -> anyhow::Result<()> {
+        if !*self.is_connected.borrow() {
+            return Err(anyhow!("Home Assistant API cannot be reached"));
+        }
+
+        self.command_sender
+            .send(command)
+            .map_err(|_| anyhow!("The Home Assistant websocket is gone"))
+    }
+
+    /// Waits up to `timeout` for the connection, so the first updates are not lost while it is being opened
+    pub async fn wait_connected(&self, timeout: Duration) -> bool {
+        let mut is_connected = self.is_connected.clone();
+
+        tokio::time::timeout(timeout, is_connected.wait_for(|is_connected| *is_connected))
+            .await
+            .is_ok_and(|result| result.is_ok())
+    }
+}
+
+impl Drop for HaWebsocket {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn run_connection(
+    url: &Url,
+    token: &str,
+    event_types: &[String],
+    connected_sender: &watch::Sender<bool>,
+    event_sender: &UnboundedSender<HaEvent>,
+    command_receiver: &mut UnboundedReceiver<Value>,
+) -> anyhow::Result<()> {
+    let (mut ws_stream, _) = connect_async(url.as_str())
+        .await
+        .with_context(|| "Failed to connect")?;
+
+    authenticate(&mut ws_stream, token).await?;
+    info!("Home Assistant websocket: Connected");
+
+    // left from a previous connection, they are stale by now
+    while command_receiver.try_recv().is_ok() {}
+
+    // every command needs a unique id for the connection
+    let mut id: u64 = 0;
+    for event_type in event_types {
+        id += 1;
+        let subscribe = json!({"id": id, "type": "subscribe_events", "event_type": event_type});
+        ws_stream.send(Message::text(subscribe.to_string())).await?;
+    }
+
+    connected_sender.send_replace(true);
+
+    let mut ping_interval = tokio::time::interval(PING_INTERVAL);
+    // the first tick is immediate
+    ping_interval.tick().await;
+    let mut is_awaiting_pong = false;
+
+    loop {
+        tokio::select! {
+            message = ws_stream.next() => {
+                let Some(message) = message else {
+                    return Err(anyhow!("The connection was closed"));
+                };
+                let Message::Text(text) = message? else { continue };
+                let answer: Value = serde_json::from_str(&text)?;
+
+                match answer["type"].as_str() {
+                    Some("event") => {
+                        let event = HaEvent {
+                            event_type: answer["event"]["event_type"].as_str().unwrap_or_default().to_string(),
+                            data: answer["event"]["data"].clone(),
+                        };
+                        // nobody listening is not an error for the connection
+                        let _ = event_sender.send(event);
+                    }
+                    Some("pong") => is_awaiting_pong = false,
+                    Some("result") if answer["success"] == false => {
+                        error!("Home Assistant websocket: Command {} failed: {}", answer["id"], answer["error"]);
+                    }
+                    _ => {}
+                }
+            }
+            command = command_receiver.recv() => {
+                let Some(mut command) = command else {
+                    return Err(anyhow!("The Home Assistant websocket is gone"));
+                };
+
+                id += 1;
+                command["id"] = json!(id);
+                ws_stream.send(Message::text(command.to_string())).await?;
+            }
+            _ = ping_interval.tick() => {
+                if is_awaiting_pong {
+                    return Err(anyhow!("Home Assistant did not answer the ping"));
+                }
+
+                id += 1;
+                ws_stream.send(Message::text(json!({"id": id, "type": "ping"}).to_string())).await?;
+                is_awaiting_pong = true;
+            }
+        }
+    }
+}
+
+async fn authenticate(ws_stream: &mut HaStream, token: &str) -> anyhow::Result<()> {
+    let auth_required = read_json(ws_stream).await?;
+    if auth_required["type"] != "auth_required" {
+        return Err(anyhow!("Unexpected message from Home Assistant: {}", auth_required));
+    }
+
+    let auth = json!({"type": "auth", "access_token": token});
+    ws_stream.send(Message::text(auth.to_string())).await?;
+
+    let answer = read_json(ws_stream).await?;
+    match answer["type"].as_str() {
+        Some("auth_ok") => Ok(()),
+        Some("auth_invalid") => Err(anyhow!("Home Assistant refused the token: {}", answer["message"])),
+        _ => Err(anyhow!("Unexpected message from Home Assistant: {}", answer)),
+    }
+}
+
+async fn read_json(ws_stream: &mut HaStream) -> anyhow::Result<Value> {
+    while let Some(message) = ws_stream.next().await {
+        if let Message::Text(text) = message? {
+            return Ok(serde_json::from_str(&text)?);
+        }
+    }
+
+    Err(anyhow!("The connection was closed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::home_assistant::websocket::{run_connection, websocket_url, HaWebsocket};
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+    use std::time::Duration;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+    use tokio::sync::watch;
+    use tokio_tungstenite::tungstenite::protocol::Message;
+    use tokio_tungstenite::{accept_async, WebSocketStream};
+    use url::Url;
+
+    type FakeHaStream = WebSocketStream<TcpStream>;
+
+    async fn read_json(ws_stream: &mut FakeHaStream) -> Value {
+        loop {
+            if let Message::Text(text) = ws_stream.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    // answers the authentication like HA, then hands over the connection
+    async fn start_fake_ha(token: &'static str) -> (Url, UnboundedReceiver<FakeHaStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = websocket_url(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let (connection_sender, connection_receiver) = unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut ws_stream = accept_async(stream).await.unwrap();
+                let auth_required = json!({"type": "auth_required"}).to_string();
+                ws_stream.send(Message::text(auth_required)).await.unwrap();
+
+                let auth = read_json(&mut ws_stream).await;
+                let answer = if auth["access_token"] == token {
+                    json!({"type": "auth_ok"})
+                } else {
+                    json!({"type": "auth_invalid", "message": "Invalid access token"})
+                };
+                ws_stream.send(Message::text(answer.to_string())).await.unwrap();
+                let _ = connection_sender.send(ws_stream);
+            }
+        });
+
+        (url, connection_receiver)
+    }
+
+    #[test]
+    fn websocket_url_will_switch_scheme_and_add_path() {
+        assert_eq!(
+            websocket_url("http://homeassistant.local:8123").unwrap().as_str(),
+            "ws://homeassistant.local:8123/api/websocket"
+        );
+        assert_eq!(
+            websocket_url("https://ha.example.com/").unwrap().as_str(),
+            "wss://ha.example.com/api/websocket"
+        );
+        assert!(websocket_url("").is_err());
+        assert!(websocket_url("ftp://ha.example.com").is_err());
+    }
+
+    #[tokio::test]
+    async fn spawn_subscribed_event_will_be_forwarded() {
+        let (url, mut connections) = start_fake_ha("token").await;
+        let (event_sender, mut events) = unbounded_channel();
+        let websocket = HaWebsocket::spawn(url, "token".to_string(), vec!["test_event".to_string()], event_sender);
+
+        let mut ws_stream = connections.recv().await.unwrap();
+        let subscribe = read_json(&mut ws_stream).await;
+        assert_eq!(subscribe["type"], "subscribe_events");
+        assert_eq!(subscribe["event_type"], "test_event");
+        assert!(websocket.wait_connected(Duration::from_secs(5)).await);
+
+        let event = json!({
+            "id": subscribe["id"],
+            "type": "event",
+            "event": {"event_type": "test_event", "data": {"action": "toggle-mute"}},
+        });
+        ws_stream.send(Message::text(event.to_string())).await.unwrap();
+
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.event_type, "test_event");
+        assert_eq!(event.data["action"], "toggle-mute");
+    }
+
+    #[tokio::test]
+    async fn send_command_will_be_sent_with_an_id() {
+        let (url, mut connections) = start_fake_ha("token").await;
+        let (event_sender, _events) = unbounded_channel();
+        let websocket = HaWebsocket::spawn(url, "token".to_string(), vec![], event_sender);
+        assert!(websocket.send_command(json!({"type": "fire_event"})).is_err());
+
+        let mut ws_stream = connections.recv().await.unwrap();
+        assert!(websocket.wait_connected(Duration::from_secs(5)).await);
+        websocket
+            .send_command(json!({"type": "fire_event", "event_type": "test_event"}))
+            .unwrap();
+
+        let command = read_json(&mut ws_stream).await;
+        assert_eq!(command["type"], "fire_event");
+        assert_eq!(command["event_type"], "test_event");
+        assert!(command["id"].is_u64());
+    }
+
+    #[tokio::test]
+    async fn run_connection_invalid_token_will_fail() {
+        let (url, _connections) = start_fake_ha("token").await;
+        let (connected_sender, is_connected) = watch::channel(false);
+        let (event_sender, _events) = unbounded_channel();
+        let (_command_sender, mut command_receiver) = unbounded_channel();
+
+        let result = run_connection(
+            &url,
+            "wrong",
+            &[],
+            &connected_sender,
+            &event_sender,
+            &mut command_receiver,
+        )
+        .await;
+
+        assert!(result.unwrap_err().to_string().contains("refused the token"));
+        assert!(!*is_connected.borrow());
+    }
+}
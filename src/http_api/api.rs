@@ -0,0 +1,342 @@
+use crate::http_api::configuration::HttpApiConfiguration;
+use crate::http_api::events::{create_event_stream, parse_fields, StateChange};
+use crate::teams_ws::actions::{ActionSender, TeamsAction};
+use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey, TeamsStates};
+use crate::traits::Listener;
+use async_trait::async_trait;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use log::{error, info};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio::task;
+
+// changes are small and rare, this only has to absorb a client being briefly slow
+const EVENTS_CAPACITY: usize = 32;
+
+struct HttpState {
+    bearer_token: String,
+    teams_states: Arc<TeamsStates>,
+    action_sender: ActionSender,
+    teams_connected: AtomicBool,
+    events: broadcast::Sender<StateChange>,
+}
+
+/// Local REST API, so scripts and Stream Deck plugins on the same machine do not need MQTT or HA:
+/// - `GET /health`
+/// - `GET /state`
+/// - `GET /events?fields=<comma separated fields>`, Server-Sent Events pushing every change
+/// - `POST /actions/<action>`, e.g. `toggle-mute` or `send-reaction:like`, answers once Teams has
+pub struct HttpApi {
+    state: Arc<HttpState>,
+    // what the event clients have seen so far, to send them the old values
+    last_snapshot: Mutex<TeamsSnapshot>,
+}
+
+impl HttpApi {
+    pub fn new(
+        http_api_configuration: HttpApiConfiguration,
+        teams_states: Arc<TeamsStates>,
+        action_sender: ActionSender,
+    ) -> Self {
+        let state = Arc::new(HttpState {
+            bearer_token: http_api_configuration.bearer_token,
+            teams_states,
+            action_sender,
+            teams_connected: AtomicBool::new(false),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+        });
+
+        task::spawn(serve(http_api_configuration.port, create_router(state.clone())));
+
+        Self {
+            state,
+            last_snapshot: Mutex::new(TeamsSnapshot::default()),
+        }
+    }
+}
+
+async fn serve(port: u16, router: Router) {
+    // never exposed to the network, the API can control the user's meetings
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+
+    let listener = match TcpListener::bind(address).await {
+        Ok(listener) => listener,
+        Err(error) => {
+            error!("HTTP API: Unable to listen on {}: {}", address, error);
+            return;
+        }
+    };
+
+    info!("HTTP API: Listening on {}", address);
+
+    if let Err(error) = axum::serve(listener, router).await {
+        error!("HTTP API: Server stopped: {}", error);
+    }
+}
+
+fn create_router(state: Arc<HttpState>) -> Router {
+    let authenticated = Router::new()
+        .route("/state", get(get_state))
+        .route("/events", get(get_events))
+        .route("/actions/:action", post(post_action))
+        .route_layer(middleware::from_fn_with_state(state.clone(), authenticate));
+
+    Router::new()
+        .route("/health", get(get_health))
+        .merge(authenticated)
+        .with_state(state)
+}
+
+async fn authenticate(State(state): State<Arc<HttpState>>, request: Request, next: Next) -> Response {
+    // never started without a token, this only guards against an empty one matching `Bearer `
+    if state.bearer_token.is_empty() {
+        return error_response(StatusCode::UNAUTHORIZED, "The HTTP API has no bearer token");
+    }
+
+    let expected = format!("Bearer {}", state.bearer_token);
+    let is_authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .is_some_and(|value| value.as_bytes() == expected.as_bytes());
+
+    if is_authorized {
+        next.run(request).await
+    } else {
+        error_response(StatusCode::UNAUTHORIZED, "Invalid or missing bearer token")
+    }
+}
+
+fn error_response(status: StatusCode, reason: &str) -> Response {
+    (status, Json(json!({ "error": reason }))).into_response()
+}
+
+async fn get_health(State(state): State<Arc<HttpState>>) -> Json<Value> {
+    Json(json!({
+        "status": "ok",
+        "teams_connected": state.teams_connected.load(Ordering::Relaxed),
+    }))
+}
+
+async fn get_state(State(state): State<Arc<HttpState>>) -> Json<Value> {
+    Json(snapshot_to_json(&state.teams_states.snapshot()))
+}
+
+fn snapshot_to_json(snapshot: &TeamsSnapshot) -> Value {
+    let values: Map<String, Value> = TeamsStateKey::ALL
+        .into_iter()
+        .map(|key| (key.name().to_string(), state_to_json(snapshot, key)))
+        .collect();
+
+    Value::Object(values)
+}
+
+pub fn state_to_json(snapshot: &TeamsSnapshot, key: TeamsStateKey) -> Value {
+    if key.is_binary() {
+        Value::Bool(snapshot.get(key))
+    } else {
+        Value::String(snapshot.state_str(key))
+    }
+}
+
+async fn get_events(
+    State(state): State<Arc<HttpState>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let fields = match parse_fields(query.get("fields").map(String::as_str)) {
+        Ok(fields) => fields,
+        Err(error) => return error_response(StatusCode::BAD_REQUEST, &error),
+    };
+
+    // subscribed first, so no change can happen between the snapshot and the subscription
+    let receiver = state.events.subscribe();
+    create_event_stream(&state.teams_states.snapshot(), receiver, fields).into_response()
+}
+
+async fn post_action(State(state): State<Arc<HttpState>>, Path(action): Path<String>) -> Response {
+    let action = match action.parse::<TeamsAction>() {
+        Ok(action) => action,
+        Err(error) => return error_response(StatusCode::NOT_FOUND, &error.to_string()),
+    };
+
+    // queued actions are discarded when Teams connects, so the caller would only get an answer much later
+    if !state.teams_connected.load(Ordering::Relaxed) {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, "Teams is not connected");
+    }
+
+    info!("HTTP API requested {}", action);
+
+    match state.action_sender.request(action).await {
+        Ok(()) => Json(json!({ "action": action.to_string() })).into_response(),
+        Err(error) => error_response(StatusCode::BAD_GATEWAY, &error.to_string()),
+    }
+}
+
+#[async_trait]
+impl Listener for HttpApi {
+    fn name(&self) -> &'static str {
+        "HTTP API"
+    }
+
+    // `changed` holds every key on forced updates, so the changes are computed against what clients have seen
+    async fn notify_changed(&self, snapshot: &TeamsSnapshot, _: &[TeamsStateKey]) -> anyhow::Result<()> {
+        let mut last_snapshot = self.last_snapshot.lock().unwrap();
+        let change = StateChange::between(&last_snapshot, snapshot);
+
+        // sending only fails when no client is listening
+        if !change.changes.is_empty() {
+            let _ = self.state.events.send(change);
+        }
+
+        *last_snapshot = snapshot.clone();
+        Ok(())
+    }
+
+    async fn notify_action_failed(&self, _: &TeamsAction, _: &str) -> anyhow::Result<()> {
+        // the caller of the action already got the failure as a response
+        Ok(())
+    }
+
+    async fn notify_teams_connected(&self, is_connected: bool) -> anyhow::Result<()> {
+        self.state.teams_connected.store(is_connected, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn reconnect(&mut self) {
+        // nothing to reconnect, the server runs on its own
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http_api::api::{create_router, HttpState};
+    use crate::teams_ws::actions::{ActionRequest, ActionSender, TeamsAction};
+    use crate::teams_ws::states::{TeamsStateKey, TeamsStates};
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use axum::Router;
+    use serde_json::Value;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use tokio::sync::broadcast;
+    use tokio::sync::mpsc::UnboundedReceiver;
+    use tower::ServiceExt;
+
+    fn create_test_router(
+        bearer_token: &str,
+    ) -> (Router, Arc<HttpState>, UnboundedReceiver<ActionRequest>) {
+        let (action_sender, action_receiver) = ActionSender::new();
+        let state = Arc::new(HttpState {
+            bearer_token: bearer_token.to_string(),
+            teams_states: Arc::new(TeamsStates::default()),
+            action_sender,
+            teams_connected: AtomicBool::new(true),
+            events: broadcast::channel(1).0,
+        });
+
+        (create_router(state.clone()), state, action_receiver)
+    }
+
+    async fn send(router: Router, method: &str, uri: &str, token: Option<&str>) -> (StatusCode, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+
+        let response = router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn get_state_will_return_snapshot() {
+        let (router, state, _) = create_test_router("secret");
+        state
+            .teams_states
+            .update(|snapshot| snapshot.with(TeamsStateKey::IsMuted, true));
+
+        let (status, body) = send(router, "GET", "/state", Some("secret")).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["is_muted"], true);
+        assert_eq!(body["is_video_on"], false);
+        assert_eq!(body["availability"], "Unknown");
+    }
+
+    #[tokio::test]
+    async fn get_state_wrong_token_will_be_unauthorized() {
+        let (router, _, _) = create_test_router("secret");
+
+        let (status, _) = send(router, "GET", "/state", Some("guess")).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn get_state_empty_configured_token_will_be_unauthorized() {
+        let (router, _, _) = create_test_router("");
+
+        let (status, _) = send(router, "GET", "/state", Some("")).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn get_health_will_not_require_token() {
+        let (router, _, _) = create_test_router("secret");
+
+        let (status, body) = send(router, "GET", "/health", None).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["teams_connected"], true);
+    }
+
+    #[tokio::test]
+    async fn get_events_unknown_field_will_return_bad_request() {
+        let (router, _, _) = create_test_router("secret");
+
+        let (status, body) = send(router, "GET", "/events?fields=is_muted,is_happy", Some("secret")).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "Unknown field 'is_happy'");
+    }
+
+    #[tokio::test]
+    async fn post_action_will_answer_with_teams_response() {
+        let (router, _, mut action_receiver) = create_test_router("secret");
+        tokio::spawn(async move {
+            let request = action_receiver.recv().await.unwrap();
+            assert_eq!(request.action, TeamsAction::ToggleMute);
+            let _ = request.responder.unwrap().send(Ok(()));
+        });
+
+        let (status, body) = send(router, "POST", "/actions/toggle-mute", Some("secret")).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["action"], "toggle-mute");
+    }
+
+    #[tokio::test]
+    async fn post_action_unknown_action_will_return_not_found() {
+        let (router, _, _) = create_test_router("secret");
+
+        let (status, _) = send(router, "POST", "/actions/join-call", Some("secret")).await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
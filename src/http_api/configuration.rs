@@ -0,0 +1,20 @@
+pub const HTTP_API: &str = "HTTP API";
+pub const HTTP_API_ENABLED: &str = "Enabled";
+pub const HTTP_API_PORT: &str = "Port";
+pub const HTTP_API_BEARER_TOKEN: &str = "Bearer Token";
+pub const HTTP_API_PORT_DEFAULT: u16 = 8125;
+
+pub struct HttpApiConfiguration {
+    pub enabled: bool,
+    pub port: u16,
+    // required, as any web page opened in a browser can send requests to localhost
+    pub bearer_token: String,
+}
+
+pub fn create_http_api_configuration() -> HttpApiConfiguration {
+    HttpApiConfiguration {
+        enabled: false,
+        port: HTTP_API_PORT_DEFAULT,
+        bearer_token: "".to_string(),
+    }
+}
@@ -0,0 +1,182 @@
+use crate::http_api::api::state_to_json;
+use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, Stream, StreamExt};
+use log::warn;
+use serde_json::{json, Map, Value};
+use std::convert::Infallible;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+const EVENT_STATE: &str = "state";
+const EVENT_CHANGE: &str = "change";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldChange {
+    pub key: TeamsStateKey,
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Clone, Debug)]
+pub struct StateChange {
+    pub changes: Vec<FieldChange>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl StateChange {
+    pub fn between(previous: &TeamsSnapshot, current: &TeamsSnapshot) -> Self {
+        let changes = current
+            .diff(previous)
+            .into_iter()
+            .map(|key| FieldChange {
+                key,
+                old: state_to_json(previous, key),
+                new: state_to_json(current, key),
+            })
+            .collect();
+
+        Self {
+            changes,
+            timestamp: Utc::now(),
+        }
+    }
+
+    // None when none of the subscribed fields changed
+    fn to_event_data(&self, fields: &[TeamsStateKey]) -> Option<Value> {
+        let changes: Vec<Value> = self
+            .changes
+            .iter()
+            .filter(|change| fields.contains(&change.key))
+            .map(|change| {
+                json!({
+                    "field": change.key.name(),
+                    "old": change.old,
+                    "new": change.new,
+                })
+            })
+            .collect();
+
+        if changes.is_empty() {
+            return None;
+        }
+
+        Some(json!({
+            "timestamp": self.timestamp.to_rfc3339(),
+            "changes": changes,
+        }))
+    }
+}
+
+/// Parses the comma separated `fields` query parameter, every field is subscribed to when it is missing
+pub fn parse_fields(fields: Option<&str>) -> Result<Vec<TeamsStateKey>, String> {
+    let Some(fields) = fields.filter(|fields| !fields.trim().is_empty()) else {
+        return Ok(TeamsStateKey::ALL.to_vec());
+    };
+
+    fields
+        .split(',')
+        .map(|field| {
+            TeamsStateKey::from_name(field.trim()).ok_or_else(|| format!("Unknown field '{}'", field))
+        })
+        .collect()
+}
+
+/// Sends the current values of the subscribed fields as a `state` event, then a `change` event whenever
+/// one of them changes
+pub fn create_event_stream(
+    snapshot: &TeamsSnapshot,
+    receiver: broadcast::Receiver<StateChange>,
+    fields: Vec<TeamsStateKey>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let state: Map<String, Value> = fields
+        .iter()
+        .map(|key| (key.name().to_string(), state_to_json(snapshot, *key)))
+        .collect();
+    let state_event = Event::default().event(EVENT_STATE).data(
+        json!({
+            "timestamp": Utc::now().to_rfc3339(),
+            "state": state,
+        })
+        .to_string(),
+    );
+
+    let change_events = stream::unfold((receiver, fields), |(mut receiver, fields)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(change) => {
+                    if let Some(data) = change.to_event_data(&fields) {
+                        let event = Event::default().event(EVENT_CHANGE).data(data.to_string());
+                        return Some((Ok(event), (receiver, fields)));
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("HTTP API: Event client is too slow, {} changes were skipped", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream::once(async { Ok(state_event) }).chain(change_events))
+        .keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http_api::events::{parse_fields, FieldChange, StateChange};
+    use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
+    use serde_json::json;
+
+    #[test]
+    fn between_will_hold_old_and_new_values() {
+        let previous = TeamsSnapshot::default().with(TeamsStateKey::IsMuted, true);
+        let current = TeamsSnapshot::default().with(TeamsStateKey::IsVideoOn, true);
+
+        let change = StateChange::between(&previous, &current);
+
+        assert_eq!(
+            change.changes,
+            vec![
+                FieldChange {
+                    key: TeamsStateKey::IsMuted,
+                    old: json!(true),
+                    new: json!(false),
+                },
+                FieldChange {
+                    key: TeamsStateKey::IsVideoOn,
+                    old: json!(false),
+                    new: json!(true),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn to_event_data_will_only_include_subscribed_fields() {
+        let previous = TeamsSnapshot::default();
+        let current = TeamsSnapshot::default()
+            .with(TeamsStateKey::IsMuted, true)
+            .with(TeamsStateKey::IsVideoOn, true);
+        let change = StateChange::between(&previous, &current);
+
+        let data = change.to_event_data(&[TeamsStateKey::IsVideoOn]).unwrap();
+
+        assert_eq!(data["changes"].as_array().unwrap().len(), 1);
+        assert_eq!(data["changes"][0]["field"], "is_video_on");
+        assert_eq!(data["changes"][0]["old"], false);
+        assert_eq!(data["changes"][0]["new"], true);
+        assert!(change.to_event_data(&[TeamsStateKey::IsSharing]).is_none());
+    }
+
+    #[test]
+    fn parse_fields_missing_will_subscribe_to_all() {
+        assert_eq!(parse_fields(None).unwrap(), TeamsStateKey::ALL.to_vec());
+    }
+
+    #[test]
+    fn parse_fields_unknown_field_will_fail() {
+        assert!(parse_fields(Some("is_muted,is_happy")).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::home_assistant::configuration::HaEntity;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+
+const APP_NAME: &str = "teams-status-rs";
+
+/// The name given to this PC in the `General` section, it namespaces the HA entities, the MQTT topics and the MQTT
+/// client id so several PCs can share the same HA and broker. Nothing is prefixed when it is empty.
+#[derive(Clone)]
+pub struct Instance {
+    pub name: String,
+    // the name in lower case, with `_` instead of anything else than letters and digits, e.g. `office_pc`
+    pub id: String,
+    pub host_name: String,
+}
+
+pub fn create_instance(name: &str) -> Instance {
+    Instance {
+        name: name.trim().to_string(),
+        id: instance_id(name),
+        host_name: host_name(),
+    }
+}
+
+impl Instance {
+    /// `binary_sensor.teams_muted` becomes `binary_sensor.office_pc_teams_muted`
+    pub fn prefix_entity_id(&self, entity_id: &str) -> String {
+        match entity_id.split_once('.') {
+            Some((domain, object_id)) => format!("{}.{}", domain, self.prefix(object_id, "_")),
+            None => self.prefix(entity_id, "_"),
+        }
+    }
+
+    /// e.g. `office_pc/teams-status` for a topic or `office_pc_teams_status` for an id
+    pub fn prefix(&self, value: &str, separator: &str) -> String {
+        if self.id.is_empty() {
+            return value.to_string();
+        }
+
+        format!("{}{}{}", self.id, separator, value)
+    }
+
+    /// `Teams Muted` becomes `Office PC Teams Muted`
+    pub fn prefix_ha_name(&self, name: &str) -> String {
+        if self.name.is_empty() {
+            return name.to_string();
+        }
+
+        format!("{} {}", self.name, name)
+    }
+
+    pub fn prefix_ha_entity(&self, ha_entity: &mut HaEntity) {
+        ha_entity.id = self.prefix_entity_id(&ha_entity.id);
+        ha_entity.friendly_name = self.prefix_ha_name(&ha_entity.friendly_name);
+    }
+
+    /// Published by each instance, so the PCs sharing the same HA or broker can be listed
+    pub fn identity(&self) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("instance".to_string(), self.label().to_string()),
+            ("instance_id".to_string(), self.id.clone()),
+            ("host_name".to_string(), self.host_name.clone()),
+            ("app".to_string(), APP_NAME.to_string()),
+            ("version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+        ])
+    }
+
+    /// The name, or the host name when there is none
+    pub fn label(&self) -> &str {
+        if self.name.is_empty() {
+            &self.host_name
+        } else {
+            &self.name
+        }
+    }
+
+    /// Commands without an instance are for every instance
+    pub fn is_target(&self, instance: Option<&str>) -> bool {
+        instance.is_none_or(|instance| {
+            instance == self.name || instance == self.id || instance == self.host_name
+        })
+    }
+}
+
+fn instance_id(name: &str) -> String {
+    name.to_lowercase()
+        .split(|character: char| !character.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Name of the PC, so the entities of several PCs can be told apart
+pub fn host_name() -> String {
+    env::var("COMPUTERNAME")
+        .or_else(|_| env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|host_name| host_name.trim().to_string())
+        .filter(|host_name| !host_name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::home_assistant::configuration::create_ha_entity;
+    use crate::instance::create_instance;
+
+    #[test]
+    fn create_instance_name_will_result_in_id() {
+        assert_eq!(create_instance("Office PC").id, "office_pc");
+        assert_eq!(create_instance(" Bob's Laptop #2 ").id, "bob_s_laptop_2");
+        assert_eq!(create_instance("").id, "");
+    }
+
+    #[test]
+    fn prefix_with_name_will_namespace_ids_and_topics() {
+        let instance = create_instance("Office PC");
+        let mut ha_entity = create_ha_entity("binary_sensor.teams_muted", "Teams Muted", "", "");
+
+        instance.prefix_ha_entity(&mut ha_entity);
+
+        assert_eq!(ha_entity.id, "binary_sensor.office_pc_teams_muted");
+        assert_eq!(ha_entity.friendly_name, "Office PC Teams Muted");
+        assert_eq!(instance.prefix("teams-status", "/"), "office_pc/teams-status");
+    }
+
+    #[test]
+    fn prefix_without_name_will_keep_values() {
+        let instance = create_instance("");
+        let mut ha_entity = create_ha_entity("binary_sensor.teams_muted", "Teams Muted", "", "");
+
+        instance.prefix_ha_entity(&mut ha_entity);
+
+        assert_eq!(ha_entity.id, "binary_sensor.teams_muted");
+        assert_eq!(ha_entity.friendly_name, "Teams Muted");
+        assert_eq!(instance.prefix("teams-status", "/"), "teams-status");
+    }
+
+    #[test]
+    fn is_target_other_instance_will_be_false() {
+        let instance = create_instance("Office PC");
+
+        assert!(instance.is_target(None));
+        assert!(instance.is_target(Some("office_pc")));
+        assert!(instance.is_target(Some("Office PC")));
+        assert!(!instance.is_target(Some("living_room_pc")));
+    }
+}
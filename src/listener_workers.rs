@@ -0,0 +1,328 @@
+use crate::teams_ws::actions::TeamsAction;
+use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey, TeamsStates};
+use crate::traits::Listener;
+use log::{error, info};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
+use tokio::task;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// the oldest failures are dropped past this, while a listener is unreachable
+const MAX_PENDING_ACTIONS_FAILED: usize = 16;
+
+#[derive(Clone, Copy, Default)]
+struct StateVersion {
+    version: u64,
+    // bumped whenever a full update is requested, so it is not lost when versions are coalesced
+    force_generation: u64,
+}
+
+enum ListenerEvent {
+    ActionFailed(TeamsAction, String),
+    TeamsConnected(bool),
+}
+
+/// Events not delivered yet, retried along with the states
+#[derive(Default)]
+struct PendingEvents {
+    // only the latest one matters
+    teams_connected: Option<bool>,
+    actions_failed: VecDeque<(TeamsAction, String)>,
+}
+
+impl PendingEvents {
+    fn add(&mut self, event: ListenerEvent) {
+        match event {
+            ListenerEvent::ActionFailed(action, reason) => {
+                if self.actions_failed.len() == MAX_PENDING_ACTIONS_FAILED {
+                    self.actions_failed.pop_front();
+                }
+                self.actions_failed.push_back((action, reason));
+            }
+            ListenerEvent::TeamsConnected(is_connected) => self.teams_connected = Some(is_connected),
+        }
+    }
+}
+
+/// Runs every listener in its own task, a failing listener retries with backoff on its own, without holding back
+/// the others or the Teams connection. States are coalesced, a listener that comes back only gets the latest one.
+pub struct ListenerWorkers {
+    state_sender: watch::Sender<StateVersion>,
+    event_senders: Vec<UnboundedSender<ListenerEvent>>,
+}
+
+impl ListenerWorkers {
+    pub fn spawn(listeners: Vec<Box<dyn Listener>>, teams_states: Arc<TeamsStates>) -> Self {
+        let (state_sender, _) = watch::channel(StateVersion::default());
+
+        let event_senders = listeners
+            .into_iter()
+            .map(|listener| {
+                let (event_sender, event_receiver) = unbounded_channel();
+                task::spawn(run_listener(
+                    listener,
+                    teams_states.clone(),
+                    state_sender.subscribe(),
+                    event_receiver,
+                ));
+                event_sender
+            })
+            .collect();
+
+        Self {
+            state_sender,
+            event_senders,
+        }
+    }
+
+    pub fn notify_changed(&self, force_update: bool) {
+        self.state_sender.send_modify(|state_version| {
+            state_version.version += 1;
+
+            if force_update {
+                state_version.force_generation += 1;
+            }
+        });
+    }
+
+    pub fn notify_action_failed(&self, action: &TeamsAction, reason: &str) {
+        self.send_event(|| ListenerEvent::ActionFailed(*action, reason.to_string()));
+    }
+
+    pub fn notify_teams_connected(&self, is_connected: bool) {
+        self.send_event(|| ListenerEvent::TeamsConnected(is_connected));
+    }
+
+    fn send_event(&self, create_event: impl Fn() -> ListenerEvent) {
+        for event_sender in &self.event_senders {
+            if event_sender.send(create_event()).is_err() {
+                error!("A listener worker has stopped, unable to send it the event");
+            }
+        }
+    }
+}
+
+async fn run_listener(
+    mut listener: Box<dyn Listener>,
+    teams_states: Arc<TeamsStates>,
+    mut state_receiver: watch::Receiver<StateVersion>,
+    mut event_receiver: UnboundedReceiver<ListenerEvent>,
+) {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut pending = PendingEvents::default();
+    let mut has_pending_state = false;
+    // the first update, and the first one after a failure, sends everything
+    let mut force_update = true;
+    let mut force_generation = 0;
+    let mut last_delivered: Option<TeamsSnapshot> = None;
+
+    loop {
+        // the events received while backing off replace the older ones
+        while let Ok(event) = event_receiver.try_recv() {
+            pending.add(event);
+        }
+
+        let result = async {
+            if let Some(is_connected) = pending.teams_connected {
+                listener.notify_teams_connected(is_connected).await?;
+                pending.teams_connected = None;
+            }
+
+            while let Some((action, reason)) = pending.actions_failed.front() {
+                listener.notify_action_failed(action, reason).await?;
+                pending.actions_failed.pop_front();
+            }
+
+            if has_pending_state {
+                let snapshot = teams_states.snapshot();
+                let changed = match &last_delivered {
+                    Some(last_delivered) if !force_update => snapshot.diff(last_delivered),
+                    _ => TeamsStateKey::ALL.to_vec(),
+                };
+
+                if !changed.is_empty() {
+                    listener.notify_changed(&snapshot, &changed).await?;
+                }
+
+                has_pending_state = false;
+                force_update = false;
+                last_delivered = Some(snapshot);
+            }
+
+            anyhow::Ok(())
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                if backoff != INITIAL_BACKOFF {
+                    info!("{}: Back online", listener.name());
+                    backoff = INITIAL_BACKOFF;
+                }
+            }
+            // we will try to reconnect if the connection failed
+            Err(error) => {
+                error!(
+                    "{}: {}: Reconnecting and retrying in {} seconds...",
+                    listener.name(),
+                    error,
+                    backoff.as_secs()
+                );
+                force_update = true;
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                listener.reconnect();
+                continue;
+            }
+        }
+
+        tokio::select! {
+            changed = state_receiver.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+
+                let state_version = *state_receiver.borrow_and_update();
+                if state_version.force_generation != force_generation {
+                    force_generation = state_version.force_generation;
+                    force_update = true;
+                }
+                has_pending_state = true;
+            }
+            event = event_receiver.recv() => {
+                let Some(event) = event else { break };
+                pending.add(event);
+            }
+        }
+    }
+
+    info!("{}: Worker stopped", listener.name());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::listener_workers::ListenerWorkers;
+    use crate::teams_ws::actions::TeamsAction;
+    use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey, TeamsStates};
+    use crate::traits::Listener;
+    use anyhow::anyhow;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    struct CountingListener {
+        notified: Arc<AtomicU32>,
+        connected: Arc<Mutex<Vec<bool>>>,
+        failures_left: u32,
+    }
+
+    #[async_trait]
+    impl Listener for CountingListener {
+        fn name(&self) -> &'static str {
+            "Counting"
+        }
+
+        async fn notify_changed(&self, _: &TeamsSnapshot, _: &[TeamsStateKey]) -> anyhow::Result<()> {
+            if self.failures_left > 0 {
+                return Err(anyhow!("unreachable"));
+            }
+
+            self.notified.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        async fn notify_action_failed(&self, _: &TeamsAction, _: &str) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn notify_teams_connected(&self, is_connected: bool) -> anyhow::Result<()> {
+            if self.failures_left > 0 {
+                return Err(anyhow!("unreachable"));
+            }
+
+            self.connected.lock().unwrap().push(is_connected);
+            Ok(())
+        }
+
+        fn reconnect(&mut self) {
+            self.failures_left -= 1;
+        }
+    }
+
+    #[tokio::test]
+    async fn notify_changed_failing_listener_will_not_stop_the_others() {
+        let failing_notified = Arc::new(AtomicU32::new(0));
+        let working_notified = Arc::new(AtomicU32::new(0));
+        let listener_workers = ListenerWorkers::spawn(
+            vec![
+                Box::new(CountingListener {
+                    notified: failing_notified.clone(),
+                    connected: Default::default(),
+                    failures_left: 1,
+                }),
+                Box::new(CountingListener {
+                    notified: working_notified.clone(),
+                    connected: Default::default(),
+                    failures_left: 0,
+                }),
+            ],
+            Arc::new(TeamsStates::default()),
+        );
+
+        listener_workers.notify_changed(true);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(working_notified.load(Ordering::Relaxed), 1);
+        assert_eq!(failing_notified.load(Ordering::Relaxed), 0);
+
+        // the failing listener gets the state once it is back, after its backoff
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(failing_notified.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn notify_changed_burst_will_be_coalesced() {
+        let notified = Arc::new(AtomicU32::new(0));
+        let listener_workers = ListenerWorkers::spawn(
+            vec![Box::new(CountingListener {
+                notified: notified.clone(),
+                connected: Default::default(),
+                failures_left: 0,
+            })],
+            Arc::new(TeamsStates::default()),
+        );
+
+        for _ in 0..10 {
+            listener_workers.notify_changed(false);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(notified.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn notify_teams_connected_failing_listener_will_get_the_latest_one() {
+        let connected = Arc::new(Mutex::new(Vec::new()));
+        let listener_workers = ListenerWorkers::spawn(
+            vec![Box::new(CountingListener {
+                notified: Default::default(),
+                connected: connected.clone(),
+                failures_left: 1,
+            })],
+            Arc::new(TeamsStates::default()),
+        );
+
+        listener_workers.notify_teams_connected(false);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        listener_workers.notify_teams_connected(true);
+        assert!(connected.lock().unwrap().is_empty());
+
+        // retried after the backoff, without the outdated one
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(*connected.lock().unwrap(), vec![true]);
+    }
+}
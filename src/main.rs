@@ -1,28 +1,44 @@
 #![windows_subsystem = "windows"]
 
+mod busy_light;
 mod configuration;
 mod home_assistant;
+mod http_api;
+mod instance;
+mod listener_workers;
 mod logging;
 mod mqtt;
+mod presence;
+mod teams_log;
 mod teams_ws;
 mod traits;
 mod tray;
 mod utils;
+mod webhook;
 
 use std::process::exit;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use std::time;
 
-use crate::configuration::get_configuration;
+use crate::busy_light::api::BusyLightDevice;
+use crate::configuration::{get_configuration, Configuration};
+use crate::http_api::api::HttpApi;
+use crate::listener_workers::ListenerWorkers;
 use crate::logging::initialize_logging;
 use crate::mqtt::api::MqttApi;
+use crate::teams_log::api::TeamsLogApi;
+use crate::teams_ws::actions::{ActionRequest, ActionSender};
 use crate::teams_ws::api::TeamsAPI;
+use crate::teams_ws::states::TeamsStates;
 use crate::traits::Listener;
 use crate::tray::create_tray;
+use crate::webhook::api::WebhookApi;
 use anyhow::Result;
 use home_assistant::api::HaApi;
 use log::{error, info};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::task;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -30,20 +46,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("--------------------");
     info!("Application starting");
 
-    // to toggle mute from the tray icon, and let Teams allow the application to listen to its websocket
-    let toggle_mute = Arc::new(AtomicBool::new(false));
+    // to send actions to Teams from the tray icon, toggling mute also lets Teams allow the application to
+    // listen to its websocket
+    let (action_sender, mut action_receiver) = ActionSender::new();
     // used by tray icon to allow exiting the application
     let is_running = Arc::new(AtomicBool::new(true));
-    let _tray = create_tray(is_running.clone(), toggle_mute.clone());
+    let _tray = create_tray(is_running.clone(), action_sender.clone());
     let five_seconds = time::Duration::from_secs(5);
-    let mut save_configuration = true;
+    let conf = get_configuration(true);
+    let teams_states = Arc::new(TeamsStates::new(conf.presence.precedence.clone()));
+    let teams_log_api = conf
+        .teams_log
+        .enabled
+        .then(|| TeamsLogApi::new(&conf.teams_log, teams_states.clone()));
+    let listeners = create_listeners(conf, teams_states.clone(), action_sender);
+    // each listener has its own task and recovers on its own, so only the Teams connection is re-created below
+    let listener_workers = Arc::new(ListenerWorkers::spawn(listeners, teams_states.clone()));
+
+    if let Some(teams_log_api) = teams_log_api {
+        task::spawn(run_teams_log(teams_log_api, listener_workers.clone()));
+    }
 
-    // Aggressive to re-create the connections, but it will handle all APIs, ideal way would
-    // be to structure to app so that each API has its own loop and message queue, so when it
-    // comes back online it would pickup the items from the queue and process them.
     while is_running.load(Ordering::Relaxed) {
-        let result = run_apis(is_running.clone(), toggle_mute.clone(), save_configuration).await;
-        save_configuration = false;
+        let result = run_teams_api(
+            is_running.clone(),
+            teams_states.clone(),
+            &listener_workers,
+            &mut action_receiver,
+        )
+        .await;
 
         if result.is_err() {
             result.unwrap_or_else(|error| error!("Error encountered: {}", error));
@@ -59,26 +90,91 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     exit(0);
 }
 
-async fn run_apis(
+fn create_listeners(
+    conf: Configuration,
+    teams_states: Arc<TeamsStates>,
+    action_sender: ActionSender,
+) -> Vec<Box<dyn Listener>> {
+    let mut listeners: Vec<Box<dyn Listener>> = Vec::new();
+    let busy_light = Arc::new(conf.busy_light);
+    // published by both MQTT and HA, as an extra topic and entity
+    let published_busy_light = busy_light.enabled.then(|| busy_light.clone());
+
+    if !busy_light.device.driver.is_empty() {
+        listeners.push(Box::new(BusyLightDevice::new(busy_light.clone())));
+    }
+
+    if conf.http_api.enabled && conf.http_api.bearer_token.is_empty() {
+        error!("HTTP API: Not started, a Bearer Token is required in the HTTP API section");
+    } else if conf.http_api.enabled {
+        listeners.push(Box::new(HttpApi::new(
+            conf.http_api,
+            teams_states.clone(),
+            action_sender.clone(),
+        )));
+    }
+
+    if !conf.mqtt.url().is_empty() {
+        listeners.push(Box::new(MqttApi::new(
+            conf.mqtt,
+            published_busy_light.clone(),
+            teams_states.clone(),
+            action_sender.clone(),
+        )));
+    }
+
+    for webhook in conf.webhooks {
+        if webhook.url.is_empty() {
+            continue;
+        }
+
+        let name = webhook.name.clone();
+        match WebhookApi::new(webhook) {
+            Ok(webhook_api) => listeners.push(Box::new(webhook_api)),
+            Err(error) => error!("Webhook {} is disabled: {:#}", name, error),
+        }
+    }
+
+    // HA remains the default when nothing is configured, so its errors point the user to the configuration
+    if !conf.ha.url.is_empty() || listeners.is_empty() {
+        listeners.push(Box::new(HaApi::new(
+            conf.ha,
+            published_busy_light,
+            action_sender,
+        )));
+    }
+
+    listeners
+}
+
+async fn run_teams_api(
     is_running: Arc<AtomicBool>,
-    toggle_mute: Arc<AtomicBool>,
-    save_configuration: bool,
+    teams_states: Arc<TeamsStates>,
+    listener_workers: &ListenerWorkers,
+    action_receiver: &mut UnboundedReceiver<ActionRequest>,
 ) -> Result<()> {
-    let conf = get_configuration(save_configuration);
-    let teams_api = TeamsAPI::new(&conf.teams);
-    let listener: Box<dyn Listener> = if conf.mqtt.url().is_empty() {
-        Box::new(HaApi::new(conf.ha)?)
-    } else {
-        Box::new(MqttApi::new(conf.mqtt)?)
-    };
+    // reloaded on every connection to pick up the refreshed token
+    let conf = get_configuration(false);
+    let teams_api = TeamsAPI::new(&conf.teams, teams_states);
 
     teams_api
-        .start_listening(Arc::new(Mutex::new(listener)), is_running.clone(), toggle_mute.clone())
+        .start_listening(listener_workers, is_running.clone(), action_receiver)
         .await?;
 
     Ok(())
 }
 
+// The logs are read independently of the websocket, so one failing does not hold back the other
+async fn run_teams_log(teams_log_api: TeamsLogApi, listener_workers: Arc<ListenerWorkers>) {
+    loop {
+        if let Err(error) = teams_log_api.start_listening(&listener_workers).await {
+            error!("Unable to read the Teams logs: {}", error);
+        }
+
+        tokio::time::sleep(time::Duration::from_secs(30)).await;
+    }
+}
+
 // todo: translations & language config?
 // todo: get a better icon
 // todo: auto create versions and packages when creating tags on GitHub (if doable)
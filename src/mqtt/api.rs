@@ -1,67 +1,220 @@
-use crate::mqtt::configuration::MqttConfiguration;
-use crate::teams_ws::states::TeamsStates;
+use crate::busy_light::configuration::BusyLightConfiguration;
+use crate::mqtt::commands::MqttCommands;
+use crate::mqtt::configuration::{
+    MqttConfiguration, MQTT_AVAILABILITY_OFFLINE, MQTT_AVAILABILITY_ONLINE,
+};
+use crate::mqtt::discovery::{create_discovery_messages, DiscoveryMessage};
+use crate::teams_ws::actions::{ActionSender, TeamsAction};
+use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey, TeamsStates};
 use crate::traits::Listener;
-use crate::utils::bool_to_str;
 use async_trait::async_trait;
-use rumqttc::{AsyncClient, MqttOptions, QoS};
-use serde_json::json;
-use std::sync::atomic::Ordering;
+use log::error;
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::task;
+use tokio::task::{self, JoinHandle};
+
+// rumqttc reconnects on the next poll, this only avoids spinning while the broker is down
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
 
 pub struct MqttApi {
     client: AsyncClient,
+    // polls the connection, replaced along with the client
+    event_task: JoinHandle<()>,
     mqtt_configuration: MqttConfiguration,
+    commands: Arc<MqttCommands>,
+    discovery_messages: Arc<Vec<DiscoveryMessage>>,
+    // the application is only considered available while it can see Teams
+    teams_connected: Arc<AtomicBool>,
+    busy_light: Option<Arc<BusyLightConfiguration>>,
 }
 
 impl MqttApi {
-    pub fn new(mqtt_configuration: MqttConfiguration) -> anyhow::Result<Self> {
-        let mut mqtt_options = MqttOptions::new(
-            "teams-status",
-            mqtt_configuration.url(),
-            mqtt_configuration.port,
+    pub fn new(
+        mqtt_configuration: MqttConfiguration,
+        busy_light: Option<Arc<BusyLightConfiguration>>,
+        teams_states: Arc<TeamsStates>,
+        action_sender: ActionSender,
+    ) -> Self {
+        let commands = Arc::new(MqttCommands::new(
+            &mqtt_configuration,
+            teams_states,
+            action_sender,
+        ));
+        let discovery_messages = Arc::new(create_discovery_messages(&mqtt_configuration, busy_light.as_deref()));
+        let teams_connected = Arc::new(AtomicBool::new(false));
+        let (client, event_task) = connect(
+            &mqtt_configuration,
+            commands.clone(),
+            discovery_messages.clone(),
+            teams_connected.clone(),
         );
 
-        mqtt_options.set_credentials(&mqtt_configuration.username, &mqtt_configuration.password);
-        mqtt_options.set_keep_alive(Duration::from_secs(5));
-        let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
-
-        // mqttc requires this to work
-        task::spawn(async move { while let Ok(_) = event_loop.poll().await {} });
-
-        Ok(Self {
+        Self {
             client,
+            event_task,
             mqtt_configuration,
-        })
+            commands,
+            discovery_messages,
+            teams_connected,
+            busy_light,
+        }
+    }
+}
+
+fn connect(
+    mqtt_configuration: &MqttConfiguration,
+    commands: Arc<MqttCommands>,
+    discovery_messages: Arc<Vec<DiscoveryMessage>>,
+    teams_connected: Arc<AtomicBool>,
+) -> (AsyncClient, JoinHandle<()>) {
+    let mut mqtt_options = MqttOptions::new(
+        mqtt_configuration.client_id(),
+        mqtt_configuration.url(),
+        mqtt_configuration.port,
+    );
+
+    mqtt_options.set_credentials(&mqtt_configuration.username, &mqtt_configuration.password);
+    mqtt_options.set_keep_alive(Duration::from_secs(5));
+    // the broker flags us as offline if we crash or the PC goes to sleep
+    mqtt_options.set_last_will(LastWill::new(
+        mqtt_configuration.availability_topic(),
+        MQTT_AVAILABILITY_OFFLINE,
+        QoS::AtLeastOnce,
+        true,
+    ));
+    let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
+    let event_client = client.clone();
+    let availability_topic = mqtt_configuration.availability_topic();
+    let identity_topic = mqtt_configuration.identity_topic();
+    let identity_payload = create_identity_payload(mqtt_configuration);
+
+    // mqttc requires the event loop to be polled to work, it also hands us the command messages
+    let event_task = task::spawn(async move {
+        loop {
+            let event = match event_loop.poll().await {
+                Ok(event) => event,
+                // polling again reconnects, the subscriptions are renewed by the ConnAck
+                Err(error) => {
+                    error!("MQTT: Connection error, retrying in {:?}: {}", RECONNECT_DELAY, error);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+
+            match event {
+                // spawned, as awaiting requests here would block the event loop that processes them
+                Event::Incoming(Packet::ConnAck(_)) => {
+                    task::spawn(on_connected(
+                        event_client.clone(),
+                        commands.clone(),
+                        discovery_messages.clone(),
+                        identity_topic.clone(),
+                        identity_payload.clone(),
+                        availability_topic.clone(),
+                        teams_connected.load(Ordering::Relaxed),
+                    ));
+                }
+                Event::Incoming(Packet::Publish(publish)) => {
+                    commands.handle(&publish.topic, &publish.payload)
+                }
+                _ => {}
+            }
+        }
+    });
+
+    (client, event_task)
+}
+
+// Subscriptions do not survive a reconnection, and HA forgets discovered entities when the broker restarts,
+// so both are renewed on every connection
+async fn on_connected(
+    client: AsyncClient,
+    commands: Arc<MqttCommands>,
+    discovery_messages: Arc<Vec<DiscoveryMessage>>,
+    identity_topic: String,
+    identity_payload: String,
+    availability_topic: String,
+    teams_connected: bool,
+) {
+    for topic in commands.topics() {
+        if let Err(error) = client.subscribe(&topic, QoS::AtLeastOnce).await {
+            error!("Unable to subscribe to {}: {}", topic, error);
+        }
     }
+
+    for message in discovery_messages.iter() {
+        let result = client
+            .publish(&message.topic, QoS::AtLeastOnce, true, message.payload.clone())
+            .await;
+
+        if let Err(error) = result {
+            error!("Unable to publish discovery config {}: {}", message.topic, error);
+        }
+    }
+
+    let result = client
+        .publish(&identity_topic, QoS::AtLeastOnce, true, identity_payload)
+        .await;
+
+    if let Err(error) = result {
+        error!("Unable to publish the identity: {}", error);
+    }
+
+    if let Err(error) = publish_availability(&client, &availability_topic, teams_connected).await {
+        error!("Unable to publish availability: {}", error);
+    }
+}
+
+/// Every state in a single payload, read by the discovered entities through their value template
+pub fn create_state_payload(mqtt_configuration: &MqttConfiguration, snapshot: &TeamsSnapshot) -> String {
+    let mqtt_entities = &mqtt_configuration.mqtt_entities;
+    let payload: Map<String, Value> = TeamsStateKey::ALL
+        .into_iter()
+        .map(|key| (mqtt_entities.get(key).to_string(), Value::String(snapshot.state_str(key))))
+        .collect();
+
+    Value::Object(payload).to_string()
+}
+
+/// Retained on the identity topic, with the topics so the instances sharing a broker can be found and commanded
+pub fn create_identity_payload(mqtt_configuration: &MqttConfiguration) -> String {
+    let mut identity = json!(mqtt_configuration.instance.identity());
+    identity["topic"] = json!(mqtt_configuration.topic);
+    identity["command_topic"] = json!(mqtt_configuration.command_topic);
+
+    identity.to_string()
+}
+
+async fn publish_availability(
+    client: &AsyncClient,
+    availability_topic: &str,
+    is_available: bool,
+) -> anyhow::Result<()> {
+    let availability = if is_available {
+        MQTT_AVAILABILITY_ONLINE
+    } else {
+        MQTT_AVAILABILITY_OFFLINE
+    };
+
+    client
+        .publish(availability_topic, QoS::AtLeastOnce, true, availability)
+        .await?;
+
+    Ok(())
 }
 
 #[async_trait]
 impl Listener for MqttApi {
-    async fn notify_changed(&self, teams_states: &TeamsStates, _: bool) -> anyhow::Result<()> {
-        let muted = &*bool_to_str(teams_states.is_muted.load(Ordering::Relaxed));
-        let video_on = &*bool_to_str(teams_states.is_video_on.load(Ordering::Relaxed));
-        let hand_raised = &*bool_to_str(teams_states.is_hand_raised.load(Ordering::Relaxed));
-        let in_meeting = &*bool_to_str(teams_states.is_in_meeting.load(Ordering::Relaxed));
-        let recording = &*bool_to_str(teams_states.is_recording_on.load(Ordering::Relaxed));
-        let background_blurred =
-            &*bool_to_str(teams_states.is_background_blurred.load(Ordering::Relaxed));
-        let sharing = &*bool_to_str(teams_states.is_sharing.load(Ordering::Relaxed));
-        let unread_messages =
-            &*bool_to_str(teams_states.has_unread_messages.load(Ordering::Relaxed));
-
-        let mqtt_entities = &self.mqtt_configuration.mqtt_entities;
+    fn name(&self) -> &'static str {
+        "MQTT"
+    }
 
-        let payload = json!({
-            &mqtt_entities.muted:muted,
-            &mqtt_entities.video:video_on,
-            &mqtt_entities.hand_raised:hand_raised,
-            &mqtt_entities.meeting:in_meeting,
-            &mqtt_entities.recording:recording,
-            &mqtt_entities.background_blurred:background_blurred,
-            &mqtt_entities.sharing:sharing,
-            &mqtt_entities.unread_messages:unread_messages,
-        });
+    // the whole state is published every time, as it is retained for clients connecting later
+    async fn notify_changed(&self, snapshot: &TeamsSnapshot, _: &[TeamsStateKey]) -> anyhow::Result<()> {
+        let payload = create_state_payload(&self.mqtt_configuration, snapshot);
 
         // todo: log failures
         let _ = &self
@@ -70,6 +223,36 @@ impl Listener for MqttApi {
                 &self.mqtt_configuration.topic,
                 QoS::AtLeastOnce,
                 true,
+                payload,
+            )
+            .await?;
+
+        // any state can change the light, it is published along with them
+        if let Some(busy_light) = &self.busy_light {
+            self.client
+                .publish(
+                    self.mqtt_configuration.busy_light_topic(),
+                    QoS::AtLeastOnce,
+                    true,
+                    busy_light.state(snapshot).to_json().to_string(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn notify_action_failed(&self, action: &TeamsAction, reason: &str) -> anyhow::Result<()> {
+        let payload = json!({
+            "action": action.to_string(),
+            "reason": reason,
+        });
+
+        self.client
+            .publish(
+                format!("{}/error", &self.mqtt_configuration.topic),
+                QoS::AtLeastOnce,
+                false,
                 payload.to_string(),
             )
             .await?;
@@ -77,19 +260,24 @@ impl Listener for MqttApi {
         Ok(())
     }
 
+    async fn notify_teams_connected(&self, is_connected: bool) -> anyhow::Result<()> {
+        self.teams_connected.store(is_connected, Ordering::Relaxed);
+        publish_availability(
+            &self.client,
+            &self.mqtt_configuration.availability_topic(),
+            is_connected,
+        )
+        .await
+    }
+
     fn reconnect(&mut self) {
-        let mut mqtt_options = MqttOptions::new(
-            "teams-status",
-            self.mqtt_configuration.url(),
-            self.mqtt_configuration.port,
+        // the old connection would otherwise keep reconnecting with the same client id
+        self.event_task.abort();
+        (self.client, self.event_task) = connect(
+            &self.mqtt_configuration,
+            self.commands.clone(),
+            self.discovery_messages.clone(),
+            self.teams_connected.clone(),
         );
-
-        mqtt_options.set_credentials(&self.mqtt_configuration.username, &self.mqtt_configuration.password);
-        mqtt_options.set_keep_alive(Duration::from_secs(5));
-        let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
-
-        self.client = client;
-        // mqttc requires this to work
-        task::spawn(async move { while let Ok(_) = event_loop.poll().await {} });
     }
 }
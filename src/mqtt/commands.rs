@@ -0,0 +1,134 @@
+use crate::mqtt::configuration::MqttConfiguration;
+use crate::teams_ws::actions::{ActionSender, TeamsAction};
+use crate::teams_ws::states::{TeamsStateKey, TeamsStates};
+use log::{error, info};
+use std::sync::Arc;
+
+const SET_TOPIC: &str = "set";
+const PAYLOAD_ON: &str = "on";
+const PAYLOAD_OFF: &str = "off";
+const PAYLOAD_TOGGLE: &str = "toggle";
+
+/// Turns messages received on the command topics into Teams actions:
+/// - `<command topic>` takes an action name, e.g. `toggle-mute` or `send-reaction:like`
+/// - `<topic>/set/<entity>` takes `on`, `off` or `toggle`
+pub struct MqttCommands {
+    command_topic: String,
+    set_topic_prefix: String,
+    muted: String,
+    video: String,
+    hand_raised: String,
+    background_blurred: String,
+    meeting: String,
+    teams_states: Arc<TeamsStates>,
+    action_sender: ActionSender,
+}
+
+impl MqttCommands {
+    pub fn new(
+        mqtt_configuration: &MqttConfiguration,
+        teams_states: Arc<TeamsStates>,
+        action_sender: ActionSender,
+    ) -> Self {
+        let mqtt_entities = &mqtt_configuration.mqtt_entities;
+
+        Self {
+            command_topic: mqtt_configuration.command_topic.to_string(),
+            set_topic_prefix: format!("{}/{}/", mqtt_configuration.topic, SET_TOPIC),
+            muted: mqtt_entities.get(TeamsStateKey::IsMuted).to_string(),
+            video: mqtt_entities.get(TeamsStateKey::IsVideoOn).to_string(),
+            hand_raised: mqtt_entities.get(TeamsStateKey::IsHandRaised).to_string(),
+            background_blurred: mqtt_entities.get(TeamsStateKey::IsBackgroundBlurred).to_string(),
+            meeting: mqtt_entities.get(TeamsStateKey::IsInMeeting).to_string(),
+            teams_states,
+            action_sender,
+        }
+    }
+
+    pub fn topics(&self) -> Vec<String> {
+        vec![
+            self.command_topic.to_string(),
+            format!("{}+", self.set_topic_prefix),
+        ]
+    }
+
+    pub fn handle(&self, topic: &str, payload: &[u8]) {
+        let payload = String::from_utf8_lossy(payload);
+
+        let action = if topic == self.command_topic {
+            payload.parse::<TeamsAction>().map(Some)
+        } else if let Some(entity) = topic.strip_prefix(&self.set_topic_prefix) {
+            self.action_from_set(entity, &payload)
+        } else {
+            return;
+        };
+
+        match action {
+            Ok(Some(action)) => {
+                info!("MQTT requested {} on {}", action, topic);
+
+                if let Err(error) = self.action_sender.send(action) {
+                    error!("Unable to forward {} to Teams: {}", action, error);
+                }
+            }
+            Ok(None) => info!("MQTT command on {} already matches the Teams state", topic),
+            Err(error) => error!("Ignoring MQTT command on {}: {}", topic, error),
+        }
+    }
+
+    fn action_from_set(&self, entity: &str, payload: &str) -> anyhow::Result<Option<TeamsAction>> {
+        let snapshot = self.teams_states.snapshot();
+
+        let (key, action) = if entity == self.muted {
+            (TeamsStateKey::IsMuted, TeamsAction::ToggleMute)
+        } else if entity == self.video {
+            (TeamsStateKey::IsVideoOn, TeamsAction::ToggleVideo)
+        } else if entity == self.hand_raised {
+            (TeamsStateKey::IsHandRaised, TeamsAction::ToggleHand)
+        } else if entity == self.background_blurred {
+            (TeamsStateKey::IsBackgroundBlurred, TeamsAction::ToggleBackgroundBlur)
+        } else if entity == self.meeting {
+            // a meeting can only be left, not joined
+            return match desired_state(snapshot.get(TeamsStateKey::IsInMeeting), payload)? {
+                Some(false) => Ok(Some(TeamsAction::LeaveCall)),
+                _ => Ok(None),
+            };
+        } else {
+            return Err(anyhow::anyhow!("'{}' cannot be set", entity));
+        };
+
+        Ok(desired_state(snapshot.get(key), payload)?.map(|_| action))
+    }
+}
+
+/// Returns the state to switch to, or None when Teams is already in that state
+fn desired_state(current: bool, payload: &str) -> anyhow::Result<Option<bool>> {
+    let desired = match payload.trim().to_lowercase().as_str() {
+        PAYLOAD_ON => true,
+        PAYLOAD_OFF => false,
+        PAYLOAD_TOGGLE => !current,
+        _ => return Err(anyhow::anyhow!("unknown payload '{}'", payload)),
+    };
+
+    Ok((desired != current).then_some(desired))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mqtt::commands::desired_state;
+
+    #[test]
+    fn desired_state_same_as_current_will_return_none() {
+        assert_eq!(desired_state(true, "ON").unwrap(), None);
+    }
+
+    #[test]
+    fn desired_state_toggle_will_flip_current() {
+        assert_eq!(desired_state(true, "toggle").unwrap(), Some(false));
+    }
+
+    #[test]
+    fn desired_state_unknown_payload_will_fail() {
+        assert!(desired_state(false, "maybe").is_err());
+    }
+}
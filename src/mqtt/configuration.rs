@@ -1,7 +1,15 @@
+use crate::instance::{create_instance, Instance};
+use crate::teams_ws::states::TeamsStateKey;
+use std::collections::BTreeMap;
+
 pub const MQTT: &str = "MQTT";
 pub const MQTT_URL: &str = "URL";
 pub const MQTT_PORT: &str = "Port";
 pub const MQTT_TOPIC: &str = "Topic";
+pub const MQTT_COMMAND_TOPIC: &str = "Command Topic";
+pub const MQTT_DISCOVERY: &str = "Discovery";
+pub const MQTT_DISCOVERY_PREFIX: &str = "Discovery Prefix";
+pub const MQTT_NODE_ID: &str = "Node ID";
 pub const MQTT_USERNAME: &str = "Username";
 pub const MQTT_PASSWORD: &str = "Password";
 pub const MQTT_ENTITIES: &str = "MQTT Entities";
@@ -13,26 +21,90 @@ pub const MQTT_RECORDING: &str = "Recording";
 pub const MQTT_BACKGROUND_BLURRED: &str = "Background Blurred";
 pub const MQTT_SHARING: &str = "Sharing";
 pub const MQTT_UNREAD_MESSAGES: &str = "Unread Messages";
+pub const MQTT_CAN_TOGGLE_MUTE: &str = "Can Toggle Mute";
+pub const MQTT_CAN_TOGGLE_VIDEO: &str = "Can Toggle Video";
+pub const MQTT_CAN_TOGGLE_HAND: &str = "Can Toggle Hand";
+pub const MQTT_CAN_TOGGLE_BLUR: &str = "Can Toggle Blur";
+pub const MQTT_CAN_LEAVE: &str = "Can Leave";
+pub const MQTT_CAN_REACT: &str = "Can React";
+pub const MQTT_CAN_TOGGLE_SHARE_TRAY: &str = "Can Toggle Share Tray";
+pub const MQTT_CAN_TOGGLE_CHAT: &str = "Can Toggle Chat";
+pub const MQTT_CAN_STOP_SHARING: &str = "Can Stop Sharing";
+pub const MQTT_CAN_PAIR: &str = "Can Pair";
+pub const MQTT_AVAILABILITY: &str = "Availability";
+pub const MQTT_PRESENCE: &str = "Presence";
 pub const MQTT_PORT_DEFAULT: u16 = 1883;
+pub const MQTT_AVAILABILITY_ONLINE: &str = "online";
+pub const MQTT_AVAILABILITY_OFFLINE: &str = "offline";
 
 pub struct MqttEntities {
-    pub muted: String,
-    pub video: String,
-    pub hand_raised: String,
-    pub meeting: String,
-    pub recording: String,
-    pub background_blurred: String,
-    pub sharing: String,
-    pub unread_messages: String,
+    entities: BTreeMap<TeamsStateKey, String>,
+}
+
+impl MqttEntities {
+    pub fn get(&self, key: TeamsStateKey) -> &str {
+        &self.entities[&key]
+    }
+
+    pub fn set(&mut self, key: TeamsStateKey, entity: String) {
+        self.entities.insert(key, entity);
+    }
+}
+
+/// Name of the ini key holding the entity of a Teams state
+pub fn mqtt_entity_config_name(key: TeamsStateKey) -> &'static str {
+    match key {
+        TeamsStateKey::IsMuted => MQTT_MUTED,
+        TeamsStateKey::IsVideoOn => MQTT_VIDEO,
+        TeamsStateKey::IsHandRaised => MQTT_HAND_RAISED,
+        TeamsStateKey::IsInMeeting => MQTT_MEETING,
+        TeamsStateKey::IsRecordingOn => MQTT_RECORDING,
+        TeamsStateKey::IsBackgroundBlurred => MQTT_BACKGROUND_BLURRED,
+        TeamsStateKey::IsSharing => MQTT_SHARING,
+        TeamsStateKey::HasUnreadMessages => MQTT_UNREAD_MESSAGES,
+        TeamsStateKey::CanToggleMute => MQTT_CAN_TOGGLE_MUTE,
+        TeamsStateKey::CanToggleVideo => MQTT_CAN_TOGGLE_VIDEO,
+        TeamsStateKey::CanToggleHand => MQTT_CAN_TOGGLE_HAND,
+        TeamsStateKey::CanToggleBlur => MQTT_CAN_TOGGLE_BLUR,
+        TeamsStateKey::CanLeave => MQTT_CAN_LEAVE,
+        TeamsStateKey::CanReact => MQTT_CAN_REACT,
+        TeamsStateKey::CanToggleShareTray => MQTT_CAN_TOGGLE_SHARE_TRAY,
+        TeamsStateKey::CanToggleChat => MQTT_CAN_TOGGLE_CHAT,
+        TeamsStateKey::CanStopSharing => MQTT_CAN_STOP_SHARING,
+        TeamsStateKey::CanPair => MQTT_CAN_PAIR,
+        TeamsStateKey::Availability => MQTT_AVAILABILITY,
+        TeamsStateKey::Presence => MQTT_PRESENCE,
+    }
+}
+
+fn default_entity(key: TeamsStateKey) -> &'static str {
+    match key {
+        TeamsStateKey::IsMuted => "muted",
+        TeamsStateKey::IsVideoOn => "video_on",
+        TeamsStateKey::IsInMeeting => "in_meeting",
+        TeamsStateKey::IsRecordingOn => "recording_on",
+        TeamsStateKey::IsHandRaised => "hand_raised",
+        TeamsStateKey::IsBackgroundBlurred => "background_blurred",
+        TeamsStateKey::IsSharing => "sharing",
+        TeamsStateKey::HasUnreadMessages => "unread_messages",
+        // the permissions, availability and presence already use the same names as our own outputs
+        _ => key.name(),
+    }
 }
 
 pub struct MqttConfiguration {
     url: String,
     pub port: u16,
     pub topic: String,
+    pub command_topic: String,
+    pub discovery: bool,
+    pub discovery_prefix: String,
+    pub node_id: String,
     pub username: String,
     pub password: String,
     pub mqtt_entities: MqttEntities,
+    // not saved, it comes from the General section
+    pub instance: Instance,
 }
 
 impl MqttConfiguration {
@@ -40,6 +112,35 @@ impl MqttConfiguration {
         &self.url
     }
 
+    pub fn availability_topic(&self) -> String {
+        format!("{}/availability", self.topic)
+    }
+
+    pub fn busy_light_topic(&self) -> String {
+        format!("{}/busy_light", self.topic)
+    }
+
+    pub fn identity_topic(&self) -> String {
+        format!("{}/identity", self.topic)
+    }
+
+    // the broker disconnects a client when another one connects with the same id
+    pub fn client_id(&self) -> String {
+        if self.instance.id.is_empty() {
+            return "teams-status".to_string();
+        }
+
+        format!("teams-status-{}", self.instance.id)
+    }
+
+    /// Prefixes the topics and the node id, only to be called once as the prefix is not saved
+    pub fn set_instance(&mut self, instance: Instance) {
+        self.topic = instance.prefix(&self.topic, "/");
+        self.command_topic = instance.prefix(&self.command_topic, "/");
+        self.node_id = instance.prefix(&self.node_id, "_");
+        self.instance = instance;
+    }
+
     pub fn set_url(&mut self, url: String) {
         self.url = if url.to_lowercase().starts_with("mqtt://") {
             url[7..].to_string()
@@ -51,22 +152,23 @@ impl MqttConfiguration {
 
 pub fn create_mqtt_configuration() -> MqttConfiguration {
     let mqtt_entities = MqttEntities {
-        muted: "muted".to_string(),
-        video: "video_on".to_string(),
-        hand_raised: "hand_raised".to_string(),
-        meeting: "in_meeting".to_string(),
-        recording: "recording_on".to_string(),
-        background_blurred: "background_blurred".to_string(),
-        sharing: "sharing".to_string(),
-        unread_messages: "unread_messages".to_string(),
+        entities: TeamsStateKey::ALL
+            .into_iter()
+            .map(|key| (key, default_entity(key).to_string()))
+            .collect(),
     };
 
     MqttConfiguration {
         url: "".to_string(),
         port: 1883,
         topic: "teams-status".to_string(),
+        command_topic: "teams-status/cmd".to_string(),
+        discovery: true,
+        discovery_prefix: "homeassistant".to_string(),
+        node_id: "teams_status".to_string(),
         username: "".to_string(),
         password: "".to_string(),
         mqtt_entities,
+        instance: create_instance(""),
     }
 }
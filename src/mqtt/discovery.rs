@@ -0,0 +1,196 @@
+use crate::busy_light::configuration::BusyLightConfiguration;
+use crate::home_assistant::configuration::create_ha_configuration;
+use crate::mqtt::configuration::{
+    MqttConfiguration, MQTT_AVAILABILITY_OFFLINE, MQTT_AVAILABILITY_ONLINE,
+};
+use crate::teams_ws::states::TeamsStateKey;
+use serde_json::json;
+
+pub struct DiscoveryMessage {
+    pub topic: String,
+    pub payload: String,
+}
+
+/// Home Assistant MQTT discovery configs, one binary_sensor (or sensor for the availability and presence) per entity reading
+/// from the state payload, plus a sensor for the busy light when enabled
+pub fn create_discovery_messages(
+    mqtt_configuration: &MqttConfiguration,
+    busy_light: Option<&BusyLightConfiguration>,
+) -> Vec<DiscoveryMessage> {
+    if !mqtt_configuration.discovery {
+        return Vec::new();
+    }
+
+    let mut ha_configuration = create_ha_configuration();
+    // the default entities get the same prefix as the ones of the HA integration
+    ha_configuration.set_instance(mqtt_configuration.instance.clone());
+    let ha_entities = ha_configuration.entities;
+    let node_id = &mqtt_configuration.node_id;
+    let availability_topic = mqtt_configuration.availability_topic();
+    let device = json!({
+        "identifiers": [node_id],
+        "name": mqtt_configuration.instance.prefix_ha_name("Teams Status"),
+        "manufacturer": "teams-status-rs",
+        "model": "Microsoft Teams",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+
+    let mut messages: Vec<DiscoveryMessage> = TeamsStateKey::ALL
+        .into_iter()
+        .map(|key| {
+            // the HA entity holds the default name and icons
+            let entity = mqtt_configuration.mqtt_entities.get(key);
+            let ha_entity = ha_entities.get(key);
+            // keeps the entity ids the same as the ones created by the HA integration
+            let object_id = ha_entity.id.split_once('.').map_or(entity, |(_, id)| id);
+            let mut payload = json!({
+                "name": ha_entity.friendly_name,
+                "unique_id": format!("{}_{}", node_id, entity),
+                "object_id": object_id,
+                "icon": ha_entity.icons.on,
+                "state_topic": mqtt_configuration.topic,
+                "value_template": format!("{{{{ value_json.{} }}}}", entity),
+                "availability_topic": availability_topic,
+                "payload_available": MQTT_AVAILABILITY_ONLINE,
+                "payload_not_available": MQTT_AVAILABILITY_OFFLINE,
+                "device": device,
+            });
+
+            let component = if key.is_binary() {
+                payload["payload_on"] = json!("on");
+                payload["payload_off"] = json!("off");
+                "binary_sensor"
+            } else {
+                "sensor"
+            };
+
+            DiscoveryMessage {
+                topic: format!(
+                    "{}/{}/{}/{}/config",
+                    mqtt_configuration.discovery_prefix, component, node_id, entity
+                ),
+                payload: payload.to_string(),
+            }
+        })
+        .collect();
+
+    if let Some(busy_light) = busy_light {
+        let ha_entity = &busy_light.ha_entity;
+        let busy_light_topic = mqtt_configuration.busy_light_topic();
+        let object_id = ha_entity.id.split_once('.').map_or("teams_busy_light", |(_, id)| id);
+        // the rgb_color attribute comes from the same payload as the state
+        let payload = json!({
+            "name": ha_entity.friendly_name,
+            "unique_id": format!("{}_busy_light", node_id),
+            "object_id": object_id,
+            "icon": ha_entity.icons.on,
+            "state_topic": busy_light_topic,
+            "value_template": "{{ value_json.state }}",
+            "json_attributes_topic": busy_light_topic,
+            "availability_topic": availability_topic,
+            "payload_available": MQTT_AVAILABILITY_ONLINE,
+            "payload_not_available": MQTT_AVAILABILITY_OFFLINE,
+            "device": device,
+        });
+
+        messages.push(DiscoveryMessage {
+            topic: format!(
+                "{}/sensor/{}/busy_light/config",
+                mqtt_configuration.discovery_prefix, node_id
+            ),
+            payload: payload.to_string(),
+        });
+    }
+
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::busy_light::configuration::create_busy_light_configuration;
+    use crate::instance::create_instance;
+    use crate::mqtt::configuration::create_mqtt_configuration;
+    use crate::mqtt::discovery::create_discovery_messages;
+
+    #[test]
+    fn create_discovery_messages_will_create_one_per_entity() {
+        let mqtt_configuration = create_mqtt_configuration();
+        let messages = create_discovery_messages(&mqtt_configuration, None);
+
+        assert_eq!(messages.len(), 20);
+        assert_eq!(
+            messages[0].topic,
+            "homeassistant/binary_sensor/teams_status/muted/config"
+        );
+
+        let payload = json::parse(&messages[0].payload).unwrap();
+        assert_eq!(payload["unique_id"], "teams_status_muted");
+        assert_eq!(payload["object_id"], "teams_muted");
+        assert_eq!(payload["icon"], "mdi:microphone");
+        assert_eq!(payload["state_topic"], "teams-status");
+        assert_eq!(payload["value_template"], "{{ value_json.muted }}");
+        assert_eq!(payload["availability_topic"], "teams-status/availability");
+        assert_eq!(payload["payload_on"], "on");
+    }
+
+    #[test]
+    fn create_discovery_messages_availability_will_be_a_sensor() {
+        let mqtt_configuration = create_mqtt_configuration();
+        let messages = create_discovery_messages(&mqtt_configuration, None);
+        let message = messages
+            .iter()
+            .find(|message| message.topic == "homeassistant/sensor/teams_status/availability/config")
+            .unwrap();
+
+        let payload = json::parse(&message.payload).unwrap();
+        assert_eq!(payload["object_id"], "teams_availability");
+        assert!(payload["payload_on"].is_null());
+    }
+
+    #[test]
+    fn create_discovery_messages_busy_light_will_read_its_own_topic() {
+        let mqtt_configuration = create_mqtt_configuration();
+        let busy_light = create_busy_light_configuration();
+        let messages = create_discovery_messages(&mqtt_configuration, Some(&busy_light));
+        let message = messages.last().unwrap();
+
+        assert_eq!(messages.len(), 21);
+        assert_eq!(
+            message.topic,
+            "homeassistant/sensor/teams_status/busy_light/config"
+        );
+
+        let payload = json::parse(&message.payload).unwrap();
+        assert_eq!(payload["object_id"], "teams_busy_light");
+        assert_eq!(payload["state_topic"], "teams-status/busy_light");
+        assert_eq!(payload["json_attributes_topic"], "teams-status/busy_light");
+        assert_eq!(payload["value_template"], "{{ value_json.state }}");
+    }
+
+    #[test]
+    fn create_discovery_messages_instance_will_prefix_ids_and_topics() {
+        let mut mqtt_configuration = create_mqtt_configuration();
+        mqtt_configuration.set_instance(create_instance("Office PC"));
+        let messages = create_discovery_messages(&mqtt_configuration, None);
+
+        assert_eq!(
+            messages[0].topic,
+            "homeassistant/binary_sensor/office_pc_teams_status/muted/config"
+        );
+
+        let payload = json::parse(&messages[0].payload).unwrap();
+        assert_eq!(payload["name"], "Office PC Teams Muted");
+        assert_eq!(payload["unique_id"], "office_pc_teams_status_muted");
+        assert_eq!(payload["object_id"], "office_pc_teams_muted");
+        assert_eq!(payload["state_topic"], "office_pc/teams-status");
+        assert_eq!(payload["device"]["name"], "Office PC Teams Status");
+    }
+
+    #[test]
+    fn create_discovery_messages_disabled_will_create_none() {
+        let mut mqtt_configuration = create_mqtt_configuration();
+        mqtt_configuration.discovery = false;
+
+        assert!(create_discovery_messages(&mqtt_configuration, None).is_empty());
+    }
+}
@@ -1,2 +1,4 @@
 pub mod api;
+pub mod commands;
 pub mod configuration;
+pub mod discovery;
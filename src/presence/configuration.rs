@@ -0,0 +1,14 @@
+use crate::presence::fusion::PresencePrecedence;
+
+pub const PRESENCE: &str = "Presence";
+pub const PRESENCE_PRECEDENCE: &str = "Precedence";
+
+pub struct PresenceConfiguration {
+    pub precedence: PresencePrecedence,
+}
+
+pub fn create_presence_configuration() -> PresenceConfiguration {
+    PresenceConfiguration {
+        precedence: PresencePrecedence::default(),
+    }
+}
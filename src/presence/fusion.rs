@@ -0,0 +1,166 @@
+use crate::presence::states::Presence;
+use crate::teams_log::states::TSAvailability;
+use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
+use anyhow::anyhow;
+use std::fmt;
+
+// a meeting outranks whatever the user picked in Teams, being offline only wins when nothing else is known
+const DEFAULT_PRECEDENCE: [Presence; 7] = [
+    Presence::Presenting,
+    Presence::InCall,
+    Presence::Dnd,
+    Presence::Busy,
+    Presence::Away,
+    Presence::Free,
+    Presence::Offline,
+];
+
+/// Order in which the presences suggested by the websocket and the logs win over each other
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PresencePrecedence {
+    order: Vec<Presence>,
+}
+
+impl Default for PresencePrecedence {
+    fn default() -> Self {
+        Self {
+            order: DEFAULT_PRECEDENCE.to_vec(),
+        }
+    }
+}
+
+impl PresencePrecedence {
+    /// Parses a comma separated list of presences, highest first, the missing ones keep their default order
+    /// after the listed ones
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        let mut order = Vec::new();
+
+        for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let presence = Presence::from_name(name).ok_or_else(|| anyhow!("Unknown presence '{}'", name))?;
+
+            if !order.contains(&presence) {
+                order.push(presence);
+            }
+        }
+
+        for presence in DEFAULT_PRECEDENCE {
+            if !order.contains(&presence) {
+                order.push(presence);
+            }
+        }
+
+        Ok(Self { order })
+    }
+
+    pub fn fuse(&self, snapshot: &TeamsSnapshot) -> Presence {
+        let suggested = suggested_presences(snapshot);
+
+        self.order
+            .iter()
+            .copied()
+            .find(|presence| suggested.contains(presence))
+            .unwrap_or(Presence::Offline)
+    }
+}
+
+impl fmt::Display for PresencePrecedence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.order.iter().map(Presence::as_str).collect();
+        f.write_str(&names.join(", "))
+    }
+}
+
+fn suggested_presences(snapshot: &TeamsSnapshot) -> Vec<Presence> {
+    let mut suggested = Vec::new();
+
+    // the websocket flags are stale once Teams is disconnected, they are left for the logs to decide
+    if snapshot.teams_connected() {
+        if snapshot.get(TeamsStateKey::IsSharing) {
+            suggested.push(Presence::Presenting);
+        }
+
+        if snapshot.get(TeamsStateKey::IsInMeeting) {
+            suggested.push(Presence::InCall);
+        }
+
+        // Teams is running, which is all we know without the logs
+        suggested.push(Presence::Free);
+    }
+
+    let logged = match snapshot.logged_availability() {
+        TSAvailability::Available => Some(Presence::Free),
+        TSAvailability::Busy => Some(Presence::Busy),
+        TSAvailability::Away | TSAvailability::BeRightBack => Some(Presence::Away),
+        TSAvailability::DoNotDisturb | TSAvailability::Focusing => Some(Presence::Dnd),
+        TSAvailability::Presenting => Some(Presence::Presenting),
+        TSAvailability::InAMeeting | TSAvailability::OnThePhone => Some(Presence::InCall),
+        TSAvailability::Offline => Some(Presence::Offline),
+        TSAvailability::Unknown => None,
+    };
+    suggested.extend(logged);
+
+    suggested
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::presence::fusion::PresencePrecedence;
+    use crate::presence::states::Presence;
+    use crate::teams_log::states::TSAvailability;
+    use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
+
+    #[test]
+    fn fuse_meeting_will_override_logged_available() {
+        let snapshot = TeamsSnapshot::default()
+            .with_teams_connected(true)
+            .with(TeamsStateKey::IsInMeeting, true)
+            .with_availability(TSAvailability::Available);
+
+        assert_eq!(PresencePrecedence::default().fuse(&snapshot), Presence::InCall);
+    }
+
+    #[test]
+    fn fuse_disconnected_will_ignore_stale_websocket_flags() {
+        let snapshot = TeamsSnapshot::default()
+            .with(TeamsStateKey::IsInMeeting, true)
+            .with(TeamsStateKey::IsSharing, true)
+            .with_availability(TSAvailability::Offline);
+
+        assert_eq!(PresencePrecedence::default().fuse(&snapshot), Presence::Offline);
+    }
+
+    #[test]
+    fn fuse_nothing_known_will_be_offline() {
+        assert_eq!(
+            PresencePrecedence::default().fuse(&TeamsSnapshot::default()),
+            Presence::Offline
+        );
+    }
+
+    #[test]
+    fn fuse_custom_precedence_will_let_dnd_win() {
+        let precedence = PresencePrecedence::parse("DND, Presenting").unwrap();
+        let snapshot = TeamsSnapshot::default()
+            .with_teams_connected(true)
+            .with(TeamsStateKey::IsInMeeting, true)
+            .with(TeamsStateKey::IsSharing, true)
+            .with_availability(TSAvailability::DoNotDisturb);
+
+        assert_eq!(precedence.fuse(&snapshot), Presence::Dnd);
+    }
+
+    #[test]
+    fn parse_will_append_missing_presences_in_default_order() {
+        let precedence = PresencePrecedence::parse("Away, free").unwrap();
+
+        assert_eq!(
+            precedence.to_string(),
+            "Away, Free, Presenting, In Call, DND, Busy, Offline"
+        );
+    }
+
+    #[test]
+    fn parse_unknown_presence_will_fail() {
+        assert!(PresencePrecedence::parse("Away, Sleeping").is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+
+/// Single presence derived from the Teams websocket and the Teams logs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Presence {
+    Free,
+    Busy,
+    InCall,
+    Presenting,
+    Dnd,
+    Away,
+    // also used when neither the websocket nor the logs tell us anything
+    #[default]
+    Offline,
+}
+
+impl Presence {
+    pub const ALL: [Presence; 7] = [
+        Presence::Free,
+        Presence::Busy,
+        Presence::InCall,
+        Presence::Presenting,
+        Presence::Dnd,
+        Presence::Away,
+        Presence::Offline,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Presence::Free => "Free",
+            Presence::Busy => "Busy",
+            Presence::InCall => "In Call",
+            Presence::Presenting => "Presenting",
+            Presence::Dnd => "DND",
+            Presence::Away => "Away",
+            Presence::Offline => "Offline",
+        }
+    }
+
+    /// Case and spaces are ignored, so `In Call`, `InCall` and `in call` are all accepted
+    pub fn from_name(name: &str) -> Option<Self> {
+        let normalize = |name: &str| name.replace(' ', "").to_lowercase();
+        let name = normalize(name);
+
+        Presence::ALL
+            .into_iter()
+            .find(|presence| normalize(presence.as_str()) == name)
+    }
+}
+
+impl fmt::Display for Presence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::presence::states::Presence;
+
+    #[test]
+    fn from_name_will_ignore_case_and_spaces() {
+        assert_eq!(Presence::from_name("in call"), Some(Presence::InCall));
+        assert_eq!(Presence::from_name("InCall"), Some(Presence::InCall));
+        assert_eq!(Presence::from_name("dnd"), Some(Presence::Dnd));
+    }
+
+    #[test]
+    fn from_name_unknown_name_will_be_none() {
+        assert_eq!(Presence::from_name("Sleeping"), None);
+    }
+}
@@ -0,0 +1,44 @@
+use crate::listener_workers::ListenerWorkers;
+use crate::teams_log::configuration::TeamsLogConfiguration;
+use crate::teams_log::watcher::Watcher;
+use crate::teams_ws::states::TeamsStates;
+use anyhow::anyhow;
+use log::info;
+use std::sync::Arc;
+
+/// Reads the availability (Available, Busy, Away...) from the Teams logs, as the websocket does not expose it
+pub struct TeamsLogApi {
+    // the path is discovered on every attempt, Teams may be installed or its logs created later on
+    teams_log_configuration: TeamsLogConfiguration,
+    teams_states: Arc<TeamsStates>,
+}
+
+impl TeamsLogApi {
+    pub fn new(teams_log_configuration: &TeamsLogConfiguration, teams_states: Arc<TeamsStates>) -> Self {
+        Self {
+            teams_log_configuration: teams_log_configuration.clone(),
+            teams_states,
+        }
+    }
+
+    pub async fn start_listening(&self, listener_workers: &ListenerWorkers) -> anyhow::Result<()> {
+        let teams_log_path = self
+            .teams_log_configuration
+            .log_path()
+            .ok_or_else(|| anyhow!("Unable to locate the Teams logs, set their path in the configuration"))?;
+        let mut watcher = Watcher::new(teams_log_path)?;
+
+        watcher
+            .watch_teams_files(|availability| {
+                let changed = self
+                    .teams_states
+                    .update(|snapshot| snapshot.with_availability(availability));
+
+                if !changed.is_empty() {
+                    info!("Teams availability is now {}", availability);
+                    listener_workers.notify_changed(false);
+                }
+            })
+            .await
+    }
+}
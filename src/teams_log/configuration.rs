@@ -0,0 +1,31 @@
+use crate::teams_log::file_locator::{discover_log_path, log_path_candidates};
+use std::env;
+use std::path::PathBuf;
+
+pub const TEAMS_LOG: &str = "Teams Log";
+pub const TEAMS_LOG_ENABLED: &str = "Enabled";
+pub const TEAMS_LOG_PATH: &str = "Path";
+
+#[derive(Clone)]
+pub struct TeamsLogConfiguration {
+    pub enabled: bool,
+    // discovered among the known Teams locations when empty
+    pub path: String,
+}
+
+impl TeamsLogConfiguration {
+    pub fn log_path(&self) -> Option<PathBuf> {
+        if !self.path.is_empty() {
+            return Some(PathBuf::from(&self.path));
+        }
+
+        discover_log_path(log_path_candidates(|name| env::var(name).ok()))
+    }
+}
+
+pub fn create_teams_log_configuration() -> TeamsLogConfiguration {
+    TeamsLogConfiguration {
+        enabled: false,
+        path: "".to_string(),
+    }
+}
@@ -1,81 +1,97 @@
-use anyhow::{Context};
+use anyhow::Context;
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::{fs, io};
 
 pub const TEAMS_PREFIX: &str = "MSTeams_";
 const TEAMS_SUFFIX: &str = ".log";
+// the classic Teams, on Windows and Linux, writes to a single file
+const CLASSIC_TEAMS_LOG: &str = "logs.txt";
 
 // todo: When Teams is unreachable, leave the HA variables as Off
 // note: May want to move to https://github.com/uutils/coreutils/tree/main/src/uu/tail at some point
 
+pub fn is_teams_log(file_name: &str) -> bool {
+    (file_name.starts_with(TEAMS_PREFIX) && file_name.ends_with(TEAMS_SUFFIX))
+        || file_name == CLASSIC_TEAMS_LOG
+}
+
 pub fn locate_latest_log(path: &Path) -> anyhow::Result<Option<PathBuf>> {
-    let mut entries = fs::read_dir(path)
-        .context("Teams log path is invalid")?
-        .filter(|entry| {
-            let dir_entry = entry.as_ref();
-            if dir_entry.is_ok() {
-                let str = dir_entry
-                    .unwrap()
-                    .file_name()
-                    .to_str()
-                    .unwrap_or("")
-                    .to_owned();
-
-                str.starts_with(TEAMS_PREFIX) && str.ends_with(TEAMS_SUFFIX)
-            } else {
-                false
-            }
-        })
-        .map(|res| res.map(|e| e.path()))
-        .collect::<Result<Vec<_>, io::Error>>()?;
+    let mut entries = Vec::new();
+
+    for entry in fs::read_dir(path).context("Teams log path is invalid")? {
+        let entry = entry?;
+
+        if entry.file_name().to_str().is_some_and(is_teams_log) {
+            entries.push(entry.path());
+        }
+    }
 
+    // the new Teams names its files after their creation date, so the last one is the latest
     entries.sort();
-    let mut latest_file_name = "";
+    Ok(entries.pop())
+}
 
-    if entries.last().is_some() {
-        let last_entry = entries.last().unwrap();
+/// Folders where Teams writes its logs, in order of preference: new Teams, classic Teams, classic Teams on Linux
+pub fn log_path_candidates(get_env: impl Fn(&str) -> Option<String>) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    if let Some(local_app_data) = get_env("LOCALAPPDATA") {
+        candidates.push(
+            [
+                &local_app_data,
+                "Packages",
+                "MSTeams_8wekyb3d8bbwe",
+                "LocalCache",
+                "Microsoft",
+                "MSTeams",
+                "Logs",
+            ]
+            .iter()
+            .collect(),
+        );
+    }
 
-        if last_entry.to_str().is_some() {
-            latest_file_name = last_entry.to_str().unwrap();
-        }
+    if let Some(app_data) = get_env("APPDATA") {
+        candidates.push([&app_data, "Microsoft", "Teams"].iter().collect());
     }
 
-    if latest_file_name != "" {
-        return Ok(Some(PathBuf::from(latest_file_name)));
+    if let Some(home) = get_env("HOME") {
+        candidates.push([&home, ".config", "Microsoft", "Microsoft Teams"].iter().collect());
     }
 
-    Ok(None)
+    candidates
+}
+
+/// Returns the first candidate holding a Teams log
+pub fn discover_log_path(candidates: Vec<PathBuf>) -> Option<PathBuf> {
+    candidates
+        .into_iter()
+        .find(|candidate| matches!(locate_latest_log(candidate), Ok(Some(_))))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::teams_log::file_locator::{locate_latest_log, TEAMS_PREFIX};
+    use crate::teams_log::file_locator::{
+        discover_log_path, locate_latest_log, log_path_candidates, TEAMS_PREFIX,
+    };
     use chrono::{Datelike, Local};
-    use std::env::current_dir;
     use std::fs;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
-    const TEST_PATH: &str = "tests\\file_locator";
-
-    fn create_file(path: &str, file_name: String) -> PathBuf {
-        let file_path = current_dir().unwrap().join(path).join(file_name);
-        fs::write(file_path.to_str().unwrap(), "").unwrap();
-        file_path
-    }
-
-    fn cleanup_test_folder(path: &str) {
-        fs::remove_dir_all(path).ok();
+    fn create_file(path: &Path, file_name: String) -> PathBuf {
         fs::create_dir_all(path).unwrap();
+        let file_path = path.join(file_name);
+        fs::write(&file_path, "").unwrap();
+        file_path
     }
 
     #[test]
     // Mimics the naming convention of the Teams log file
     fn locate_latest_log_one_file_will_return_correct() {
-        let current_test_path = TEST_PATH.to_owned() + "/test1";
-        cleanup_test_folder(&current_test_path);
+        let folder = tempfile::tempdir().unwrap();
         let now = Local::now();
         let file_path = create_file(
-            &current_test_path,
+            folder.path(),
             format!(
                 "{}_{}-{:02}-{:02}_12-18-41.01.log",
                 TEAMS_PREFIX,
@@ -84,19 +100,18 @@ mod tests {
                 now.day()
             ),
         );
-        let found_file_path = locate_latest_log(&file_path.parent().unwrap());
+        let found_file_path = locate_latest_log(file_path.parent().unwrap());
         assert_eq!(found_file_path.unwrap().unwrap(), file_path.as_path());
     }
 
     #[test]
     // Mimics the naming convention of the Teams log file
     fn locate_latest_log_three_files_same_day_will_return_correct() {
-        let current_test_path = TEST_PATH.to_owned() + "/test2";
-        cleanup_test_folder(&current_test_path);
+        let folder = tempfile::tempdir().unwrap();
         let now = Local::now();
 
         create_file(
-            &current_test_path,
+            folder.path(),
             format!(
                 "{}_{}-{:02}-{:02}_12-17-41.01.log",
                 TEAMS_PREFIX,
@@ -106,7 +121,7 @@ mod tests {
             ),
         );
         let file_path = create_file(
-            &current_test_path,
+            folder.path(),
             format!(
                 "{}_{}-{:02}-{:02}_14-17-41.00.log",
                 TEAMS_PREFIX,
@@ -116,7 +131,7 @@ mod tests {
             ),
         );
         create_file(
-            &current_test_path,
+            folder.path(),
             format!(
                 "{}_{}-{:02}-{:02}_14-16-41.00.log",
                 TEAMS_PREFIX,
@@ -126,7 +141,7 @@ mod tests {
             ),
         );
         create_file(
-            &current_test_path,
+            folder.path(),
             format!(
                 "{}_{}-{:02}-{:02}_13-19-41.02.log",
                 TEAMS_PREFIX,
@@ -136,7 +151,48 @@ mod tests {
             ),
         );
 
-        let found_file_path = locate_latest_log(&file_path.parent().unwrap());
+        let found_file_path = locate_latest_log(file_path.parent().unwrap());
         assert_eq!(found_file_path.unwrap().unwrap(), file_path.as_path());
     }
+
+    #[test]
+    fn locate_latest_log_unrelated_files_will_return_none() {
+        let folder = tempfile::tempdir().unwrap();
+        create_file(folder.path(), "notes.txt".to_string());
+
+        assert_eq!(locate_latest_log(folder.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn discover_log_path_will_skip_candidates_without_logs() {
+        let home = tempfile::tempdir().unwrap();
+        let home_path = home.path().to_str().unwrap().to_string();
+        // classic Teams was installed once, but only the Linux folder has logs
+        fs::create_dir_all(home.path().join("AppData").join("Microsoft").join("Teams")).unwrap();
+        let linux_path = home
+            .path()
+            .join(".config")
+            .join("Microsoft")
+            .join("Microsoft Teams");
+        create_file(&linux_path, "logs.txt".to_string());
+
+        let candidates = log_path_candidates(|name| match name {
+            "APPDATA" => Some(format!("{}/AppData", home_path)),
+            "HOME" => Some(home_path.clone()),
+            _ => None,
+        });
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(discover_log_path(candidates), Some(linux_path));
+    }
+
+    #[test]
+    fn discover_log_path_no_logs_will_return_none() {
+        let home = tempfile::tempdir().unwrap();
+        let home_path = home.path().to_str().unwrap().to_string();
+
+        let candidates = log_path_candidates(|name| (name == "HOME").then(|| home_path.clone()));
+
+        assert_eq!(discover_log_path(candidates), None);
+    }
 }
@@ -1,101 +1,267 @@
-// todo: Use notify crate to know if there are changes to THAT file, and then run the seek/file parser
-// todo: Use notify crate to watch folder and pickup any new log file changes
+use crate::teams_log::file_locator::is_teams_log;
+use log::warn;
+use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc::{channel, unbounded_channel, Receiver, Sender, UnboundedReceiver};
+use tokio::task;
+use tokio::time::{interval, timeout, Instant, MissedTickBehavior};
 
-// todo: notifier on existing file, file changes, get notification
-// todo: new file in folder, get notification
+// Teams writes a burst of lines at once, they are parsed in one go once it is done
+const DEBOUNCE: Duration = Duration::from_millis(250);
+// Teams, and the other files of the folder, can be written to without a pause, the changes are still read this often
+const MAX_DEBOUNCE: Duration = Duration::from_secs(1);
+// native events are not delivered for every write on Windows, nor on some network drives
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const EVENTS_CAPACITY: usize = 10;
 
-use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::PathBuf;
-use tokio::sync::mpsc::{channel, Receiver};
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LogEvent {
+    LogAppended(PathBuf),
+    NewLogFile(PathBuf),
+    LogRemoved(PathBuf),
+}
+
+// size and last modification, a change to either means something was written
+type FileState = (u64, Option<SystemTime>);
 
+/// Watches the Teams log folder, native events only speed up the folder scans, so changes are still picked up
+/// when they are missing
 pub struct FileNotifier {
-    watcher: RecommendedWatcher,
-    pub rx: Receiver<notify::Result<Event>>,
+    // stops the native events once dropped
+    _watcher: Option<RecommendedWatcher>,
+    pub rx: Receiver<LogEvent>,
 }
 
 impl FileNotifier {
-    pub fn new() -> anyhow::Result<Self> {
-        let (tx, rx) = channel(10);
-
-        let watcher = RecommendedWatcher::new(
-            move |res| {
-                tx.blocking_send(res).unwrap();
-            }, Config::default())?;
-
-        Ok(Self { watcher, rx })
+    pub fn watch(folder: PathBuf) -> anyhow::Result<Self> {
+        Self::start(folder, true)
     }
 
-    pub fn watch(&mut self, path: Option<&PathBuf>) -> anyhow::Result<()> {
-        if path.is_none() {
-            return Ok(());
-        }
+    fn start(folder: PathBuf, use_native_events: bool) -> anyhow::Result<Self> {
+        let (native_tx, native_rx) = unbounded_channel();
+        let (tx, rx) = channel(EVENTS_CAPACITY);
 
-        let _ = &self.watcher
-            .watch(
-                path.unwrap(),
-                RecursiveMode::Recursive,
+        let watcher = if use_native_events {
+            let native_tx = native_tx.clone();
+            let mut watcher = RecommendedWatcher::new(
+                move |res: notify::Result<notify::Event>| match res {
+                    // fails once the notifier is dropped, there is no one left to notify
+                    Ok(_) => {
+                        let _ = native_tx.send(());
+                    }
+                    Err(error) => warn!("Teams log: Native file events failed: {}", error),
+                },
+                Config::default(),
             )?;
+            watcher.watch(&folder, RecursiveMode::NonRecursive)?;
+            Some(watcher)
+        } else {
+            None
+        };
+
+        // the folder is scanned before returning, so nothing written from now on is missed
+        let files = scan_folder(&folder);
+        task::spawn(async move {
+            // kept so the native events channel is never closed, even without native events
+            let _native_tx = native_tx;
+            notify_changes(folder, files, native_rx, tx).await;
+        });
 
-        Ok(())
+        Ok(Self {
+            _watcher: watcher,
+            rx,
+        })
     }
+}
+
+async fn notify_changes(
+    folder: PathBuf,
+    mut files: HashMap<PathBuf, FileState>,
+    mut native_rx: UnboundedReceiver<()>,
+    tx: Sender<LogEvent>,
+) {
+    let mut poll = interval(POLL_INTERVAL);
+    poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
-    pub fn unwatch(&mut self, path: Option<&PathBuf>) -> anyhow::Result<()> {
-        if path.is_none() {
-            return Ok(());
+    loop {
+        tokio::select! {
+            _ = poll.tick() => {}
+            _ = native_rx.recv() => {
+                debounce(&mut native_rx).await;
+                poll.reset();
+            }
         }
 
-        let _ = &self.watcher
-            .unwatch(
-                path.unwrap(),
-            )?;
+        let new_files = scan_folder(&folder);
+        for event in compare_files(&files, &new_files) {
+            if tx.send(event).await.is_err() {
+                // the watcher is gone
+                return;
+            }
+        }
+        files = new_files;
+    }
+}
 
-        Ok(())
+// waits for the burst to be over, or for MAX_DEBOUNCE after its first event
+async fn debounce(native_rx: &mut UnboundedReceiver<()>) {
+    let deadline = Instant::now() + MAX_DEBOUNCE;
+
+    loop {
+        let wait = DEBOUNCE.min(deadline.saturating_duration_since(Instant::now()));
+
+        if wait.is_zero() || !matches!(timeout(wait, native_rx.recv()).await, Ok(Some(()))) {
+            return;
+        }
     }
 }
 
+fn scan_folder(folder: &Path) -> HashMap<PathBuf, FileState> {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return HashMap::new();
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_str().is_some_and(is_teams_log))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((entry.path(), (metadata.len(), metadata.modified().ok())))
+        })
+        .collect()
+}
+
+fn compare_files(previous: &HashMap<PathBuf, FileState>, current: &HashMap<PathBuf, FileState>) -> Vec<LogEvent> {
+    let mut events: Vec<LogEvent> = current
+        .iter()
+        .filter_map(|(path, state)| match previous.get(path) {
+            None => Some(LogEvent::NewLogFile(path.clone())),
+            Some(previous_state) if previous_state != state => Some(LogEvent::LogAppended(path.clone())),
+            Some(_) => None,
+        })
+        .collect();
+
+    events.extend(
+        previous
+            .keys()
+            .filter(|path| !current.contains_key(*path))
+            .map(|path| LogEvent::LogRemoved(path.clone())),
+    );
+
+    events.sort_by_key(|event| match event {
+        LogEvent::LogRemoved(path) | LogEvent::NewLogFile(path) | LogEvent::LogAppended(path) => path.clone(),
+    });
+    events
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::teams_log::file_notifier::FileNotifier;
-    use std::env::current_dir;
-    use std::{fs};
+    use crate::teams_log::file_notifier::{FileNotifier, LogEvent};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
     use std::time::Duration;
     use tokio::time::timeout;
 
-    const TEST_PATH: &str = "tests";
+    const LOG_FILE_NAME: &str = "MSTeams_2024-02-07_14-47-09.05.log";
+
+    async fn next_event(file_notifier: &mut FileNotifier) -> LogEvent {
+        timeout(Duration::from_secs(5), file_notifier.rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
 
     #[tokio::test]
-    async fn watch_folder_edit_file_will_notify() {
-        let folder_path = current_dir().unwrap().join(TEST_PATH);
-        let mut file_notifier = FileNotifier::new().unwrap();
-        // ensure not to name the watcher "_" as it will get destroyed right away
-        file_notifier.watch(Some(&folder_path)).unwrap();
-        fs::write("C:\\Gits\\teams-status-rs\\tests\\log.txt", "test").unwrap();
-
-        let res = file_notifier.rx.recv().await.unwrap();
-        assert!(res.is_ok());
+    async fn watch_folder_new_file_will_notify() {
+        let folder = tempfile::tempdir().unwrap();
+        let mut file_notifier = FileNotifier::watch(folder.path().to_path_buf()).unwrap();
+        let log_path = folder.path().join(LOG_FILE_NAME);
+        fs::write(&log_path, "test").unwrap();
+
+        assert_eq!(next_event(&mut file_notifier).await, LogEvent::NewLogFile(log_path));
     }
 
-    // todo: use a different folder for each test as this one fails when run alongside the others
     #[tokio::test]
     async fn watch_folder_do_nothing_will_not_notify() {
-        let folder_path = current_dir().unwrap().join(TEST_PATH);
-        let mut file_notifier = FileNotifier::new().unwrap();
-        // ensure not to name the watcher "_" as it will get destroyed right away
-        file_notifier.watch(Some(&folder_path)).unwrap();
+        let folder = tempfile::tempdir().unwrap();
+        fs::write(folder.path().join(LOG_FILE_NAME), "test").unwrap();
+        let mut file_notifier = FileNotifier::watch(folder.path().to_path_buf()).unwrap();
+
+        let res = timeout(Duration::new(2, 0), file_notifier.rx.recv()).await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn watch_folder_unrelated_file_will_not_notify() {
+        let folder = tempfile::tempdir().unwrap();
+        let mut file_notifier = FileNotifier::watch(folder.path().to_path_buf()).unwrap();
+        fs::write(folder.path().join("notes.txt"), "test").unwrap();
 
-        let res = timeout(Duration::new(1, 0), file_notifier.rx.recv()).await;
+        let res = timeout(Duration::new(2, 0), file_notifier.rx.recv()).await;
         assert!(res.is_err());
     }
 
     #[tokio::test]
-    async fn watch_file_edit_file_will_notify() {
-        let file_path = current_dir().unwrap().join(TEST_PATH).join("log.txt");
-        let mut file_notifier = FileNotifier::new().unwrap();
-        // ensure not to name the watcher "_" as it will get destroyed right away
-        file_notifier.watch(Some(&file_path)).unwrap();
-        fs::write("C:\\Gits\\teams-status-rs\\tests\\log.txt", "test").unwrap();
-
-        let res = file_notifier.rx.recv().await.unwrap();
-        assert!(res.is_ok());
+    async fn watch_folder_burst_of_writes_will_notify_once() {
+        let folder = tempfile::tempdir().unwrap();
+        let log_path = folder.path().join(LOG_FILE_NAME);
+        fs::write(&log_path, "").unwrap();
+        let mut file_notifier = FileNotifier::watch(folder.path().to_path_buf()).unwrap();
+
+        let mut log_file = OpenOptions::new().append(true).open(&log_path).unwrap();
+        for line in 0..20 {
+            writeln!(log_file, "line {}", line).unwrap();
+        }
+
+        assert_eq!(next_event(&mut file_notifier).await, LogEvent::LogAppended(log_path));
+        let res = timeout(Duration::new(2, 0), file_notifier.rx.recv()).await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn watch_folder_continuous_writes_will_still_notify() {
+        let folder = tempfile::tempdir().unwrap();
+        let log_path = folder.path().join(LOG_FILE_NAME);
+        fs::write(&log_path, "").unwrap();
+        let mut file_notifier = FileNotifier::watch(folder.path().to_path_buf()).unwrap();
+
+        let writer_path = log_path.clone();
+        let writer = std::thread::spawn(move || {
+            let mut log_file = OpenOptions::new().append(true).open(writer_path).unwrap();
+            for line in 0..40 {
+                writeln!(log_file, "line {}", line).unwrap();
+                std::thread::sleep(Duration::from_millis(100));
+            }
+        });
+
+        // well before the 4 seconds of writes are over
+        let res = timeout(Duration::from_secs(2), file_notifier.rx.recv()).await;
+        assert_eq!(res.unwrap().unwrap(), LogEvent::LogAppended(log_path));
+        writer.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn watch_folder_removed_file_will_notify() {
+        let folder = tempfile::tempdir().unwrap();
+        let log_path = folder.path().join(LOG_FILE_NAME);
+        fs::write(&log_path, "").unwrap();
+        let mut file_notifier = FileNotifier::watch(folder.path().to_path_buf()).unwrap();
+        fs::remove_file(&log_path).unwrap();
+
+        assert_eq!(next_event(&mut file_notifier).await, LogEvent::LogRemoved(log_path));
+    }
+
+    #[tokio::test]
+    async fn watch_folder_without_native_events_will_poll() {
+        let folder = tempfile::tempdir().unwrap();
+        let log_path = folder.path().join(LOG_FILE_NAME);
+        fs::write(&log_path, "").unwrap();
+        let mut file_notifier = FileNotifier::start(folder.path().to_path_buf(), false).unwrap();
+        fs::write(&log_path, "test").unwrap();
+
+        assert_eq!(next_event(&mut file_notifier).await, LogEvent::LogAppended(log_path));
     }
 }
@@ -1,3 +1,5 @@
+pub mod api;
+pub mod configuration;
 pub mod file_locator;
 mod file_notifier;
 mod parser;
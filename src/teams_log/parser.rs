@@ -1,77 +1,208 @@
-// parses the given file from position x to locate the
+// parses the given file from the last position read to locate the latest availability
 // todo: there is a lot of logic in the Teams.ps1 file regarding getting the status right
 
+use crate::teams_log::states::TSAvailability;
+use anyhow::Context;
+use log::info;
 use regex::Regex;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Seek, SeekFrom};
-use std::path::PathBuf;
-
-/// Returns the availability as a string, as well as the last position parsed
-pub fn get_last_state(path: Option<&PathBuf>, position: u64) -> anyhow::Result<(Option<String>, u64)> {
-    if path.is_none() {
-        return Ok((None, 0));
+use std::fs::{File, Metadata};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+fn availability_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    // the first is written by the new Teams, the second by the classic Teams
+    REGEX.get_or_init(|| {
+        Regex::new(r"(?:UserPresenceAction:.*availability: |StatusIndicatorStateService: Added )(?<status>[a-zA-Z]*)")
+            .expect("the availability regex is valid")
+    })
+}
+
+/// Identifies the file behind the path, to notice when Teams replaces it
+#[cfg(unix)]
+fn file_identity(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_identity(metadata: &Metadata) -> Option<u64> {
+    let created = metadata.created().ok()?;
+    let since_epoch = created.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_nanos() as u64)
+}
+
+/// Follows a single log file, only parsing what was appended since the previous read
+pub struct LogParser {
+    path: PathBuf,
+    position: u64,
+    identity: Option<u64>,
+    // Teams was still writing the last line, it is parsed once complete
+    partial_line: Vec<u8>,
+}
+
+impl LogParser {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            position: 0,
+            identity: None,
+            partial_line: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
-    let mut f = File::open(path.unwrap()).unwrap();
-    f.seek(SeekFrom::Start(position)).unwrap();
-    let position = f.metadata()?.len();
-    let mut last_status: Option<String> = None;
-
-    let reader = BufReader::new(&f);
-    let re = Regex::new(r"UserPresenceAction:.*availability: (?<status>[a-zA-Z]*)").unwrap();
-    for line in reader.lines() {
-        let log_line = line.unwrap();
-        let result = re.captures(&log_line);
-
-        if result.is_some() {
-            let status = result.unwrap().name("status");
-            if status.is_some() {
-                last_status = Some(status.unwrap().as_str().to_owned());
-            }
+    /// Returns the last availability found in the complete lines appended since the previous read
+    pub fn read_last_state(&mut self) -> anyhow::Result<Option<TSAvailability>> {
+        let mut file = File::open(&self.path)
+            .with_context(|| format!("Unable to open the Teams log {}", self.path.display()))?;
+        let metadata = file.metadata()?;
+        let identity = file_identity(&metadata);
+
+        // a replaced or truncated file is read again from its start
+        if self.position > 0 && (identity != self.identity || metadata.len() < self.position) {
+            info!("Teams log {} was truncated or replaced, reading it again", self.path.display());
+            self.position = 0;
+            self.partial_line.clear();
         }
+        self.identity = identity;
+
+        file.seek(SeekFrom::Start(self.position))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+        self.position += appended.len() as u64;
+
+        self.partial_line.extend_from_slice(&appended);
+        let Some(last_line_end) = self.partial_line.iter().rposition(|byte| *byte == b'\n') else {
+            return Ok(None);
+        };
+        let complete_lines: Vec<u8> = self.partial_line.drain(..=last_line_end).collect();
+
+        Ok(parse_last_state(&String::from_utf8_lossy(&complete_lines)))
     }
+}
 
-    Ok((last_status, position))
+fn parse_last_state(lines: &str) -> Option<TSAvailability> {
+    lines
+        .lines()
+        .rev()
+        .find_map(|line| availability_regex().captures(line)?.name("status"))
+        .map(|status| TSAvailability::from_log(status.as_str()))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::teams_log::parser::get_last_state;
-    use std::env::current_dir;
+    use crate::teams_log::parser::LogParser;
+    use crate::teams_log::states::TSAvailability;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use tempfile::TempDir;
 
-    const TEST_PATH: &str = "tests";
+    const LOG_FILE_NAME: &str = "MSTeams_2024-02-07_14-47-09.05.log";
+    const LAST_UPDATE: &str =
+        "2024-02-07T14:52:10.118Z Info UserPresenceAction: {source: 1, availability: Available}\n";
+
+    // Mimics a Teams log, the last update being followed by unrelated lines
+    fn create_test_file() -> (TempDir, std::path::PathBuf, u64) {
+        let folder = tempfile::tempdir().unwrap();
+        let file_path = folder.path().join(LOG_FILE_NAME);
+        let mut content = String::new();
+        content.push_str("2024-02-07T14:47:09.050Z Info Starting Teams\n");
+        content.push_str(
+            "2024-02-07T14:47:20.301Z Info UserPresenceAction: {source: 1, availability: Busy}\n",
+        );
+        content.push_str(LAST_UPDATE);
+        let last_update_end = content.len() as u64;
+        content.push_str("2024-02-07T14:52:11.004Z Info Navigation starting: about:blank\n");
+        fs::write(&file_path, content).unwrap();
+
+        (folder, file_path, last_update_end)
+    }
+
+    fn append(file_path: &std::path::Path, content: &str) {
+        let mut log_file = OpenOptions::new().append(true).open(file_path).unwrap();
+        log_file.write_all(content.as_bytes()).unwrap();
+    }
 
     #[test]
-    fn get_last_state_test_file_will_return_correct_state() {
-        let file_path = current_dir()
-            .unwrap()
-            .join(TEST_PATH)
-            .join("MSTeams_2024-02-07_14-47-09.05.log");
-
-        let (status, _) = get_last_state(Some(&file_path), 0).unwrap();
-        assert_eq!("Available", status.unwrap());
+    fn read_last_state_test_file_will_return_correct_state() {
+        let (_folder, file_path, _) = create_test_file();
+
+        let status = LogParser::new(file_path).read_last_state().unwrap();
+        assert_eq!(TSAvailability::Available, status.unwrap());
     }
 
     #[test]
-    fn get_last_state_test_file_position_further_than_last_update_will_last_position_and_none_status() {
-        let file_path = current_dir()
-            .unwrap()
-            .join(TEST_PATH)
-            .join("MSTeams_2024-02-07_14-47-09.05.log");
-
-        let (status, position) = get_last_state(Some(&file_path), 1670531).unwrap();
-        assert!(position > 1670531);
+    fn read_last_state_test_file_position_further_than_last_update_will_last_position_and_none_status() {
+        let (_folder, file_path, last_update_end) = create_test_file();
+        let mut parser = LogParser::new(file_path);
+        parser.read_last_state().unwrap();
+        parser.position = last_update_end;
+
+        let status = parser.read_last_state().unwrap();
+        assert!(parser.position > last_update_end);
         assert_eq!(status, None);
     }
 
     #[test]
-    fn get_last_state_test_file_returned_position_will_be_higher() {
-        let file_path = current_dir()
-            .unwrap()
-            .join(TEST_PATH)
-            .join("MSTeams_2024-02-07_14-47-09.05.log");
-
-        let (_, position) = get_last_state(Some(&file_path), 0).unwrap();
-        assert!(position > 0);
+    fn read_last_state_test_file_returned_position_will_be_higher() {
+        let (_folder, file_path, _) = create_test_file();
+        let mut parser = LogParser::new(file_path);
+
+        parser.read_last_state().unwrap();
+        assert!(parser.position > 0);
+    }
+
+    #[test]
+    fn read_last_state_classic_teams_log_will_return_correct_state() {
+        let folder = tempfile::tempdir().unwrap();
+        let file_path = folder.path().join("logs.txt");
+        fs::write(
+            &file_path,
+            "Wed Feb 07 2024 14:52:10 GMT+0100 <1234> -- info -- StatusIndicatorStateService: Added DoNotDisturb (current state: Available -> DoNotDisturb)\n",
+        )
+        .unwrap();
+
+        let status = LogParser::new(file_path).read_last_state().unwrap();
+        assert_eq!(status, Some(TSAvailability::DoNotDisturb));
+    }
+
+    #[test]
+    fn read_last_state_partial_line_will_be_parsed_once_complete() {
+        let (_folder, file_path, _) = create_test_file();
+        let mut parser = LogParser::new(file_path.clone());
+        parser.read_last_state().unwrap();
+
+        append(&file_path, "2024-02-07T14:53:00.000Z Info UserPresenceAction: {source: 1, availa");
+        assert_eq!(parser.read_last_state().unwrap(), None);
+
+        append(&file_path, "bility: Away}\n");
+        assert_eq!(parser.read_last_state().unwrap(), Some(TSAvailability::Away));
+    }
+
+    #[test]
+    fn read_last_state_truncated_file_will_read_from_start() {
+        let (_folder, file_path, _) = create_test_file();
+        let mut parser = LogParser::new(file_path.clone());
+        parser.read_last_state().unwrap();
+
+        fs::write(
+            &file_path,
+            "2024-02-07T15:00:00.000Z Info UserPresenceAction: {source: 1, availability: Busy}\n",
+        )
+        .unwrap();
+
+        assert_eq!(parser.read_last_state().unwrap(), Some(TSAvailability::Busy));
+    }
+
+    #[test]
+    fn read_last_state_missing_file_will_fail() {
+        let folder = tempfile::tempdir().unwrap();
+
+        assert!(LogParser::new(folder.path().join(LOG_FILE_NAME)).read_last_state().is_err());
     }
 }
@@ -1,37 +1,120 @@
-// use std::sync::atomic::AtomicBool;
-
-// enum TSAvailability {
-//     Available,
-//     Busy,
-//     Away,
-//     BeRightBack,
-//     DoNotDisturb,
-//     Offline,
-//     Focusing,
-//     Presenting, // already in WS? Same as sharing?
-//     InAMeeting, // already in WS??
-//     OnThePhone, // already in WS??
-// }
-//
-// pub struct TeamsLogStates {
-//     pub availability: AtomicBool,
-// }
-
-// impl HelloWorld {
-//     fn as_str(&self) -> &'static str {
-//         match self {
-//             HelloWorld::Hello => "Hello",
-//             HelloWorld::World => "World"
-//         }
-//     }
-// }
-
-// If ($TeamsStatus -like "*, availability: $($_.value.keys[0])}" -or `
-//      $TeamsStatus -like "*Navigation starting: about:blank?entityType=$($_.key)*") {
-//  $Status = $($_.value.values[0])
-//  If ($Activity -eq $taInACall -And $Status -eq $tsDoNotDisturb) {
-//      $Status = $tsPresenting
-//      } ElseIf ($Activity -eq $taInACall) {
-//          $Status = $tsInAMeeting
-//      }
-// }
+use std::fmt;
+
+/// Availability as written in the Teams logs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TSAvailability {
+    Available,
+    Busy,
+    Away,
+    BeRightBack,
+    DoNotDisturb,
+    Offline,
+    Focusing,
+    Presenting,
+    InAMeeting,
+    OnThePhone,
+    // not found in the logs yet, or a value we do not know about
+    #[default]
+    Unknown,
+}
+
+impl TSAvailability {
+    pub fn from_log(value: &str) -> Self {
+        match value {
+            "Available" => TSAvailability::Available,
+            "Busy" => TSAvailability::Busy,
+            "Away" => TSAvailability::Away,
+            "BeRightBack" => TSAvailability::BeRightBack,
+            "DoNotDisturb" => TSAvailability::DoNotDisturb,
+            "Offline" => TSAvailability::Offline,
+            "Focusing" => TSAvailability::Focusing,
+            "Presenting" => TSAvailability::Presenting,
+            "InAMeeting" => TSAvailability::InAMeeting,
+            "OnThePhone" => TSAvailability::OnThePhone,
+            _ => TSAvailability::Unknown,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TSAvailability::Available => "Available",
+            TSAvailability::Busy => "Busy",
+            TSAvailability::Away => "Away",
+            TSAvailability::BeRightBack => "BeRightBack",
+            TSAvailability::DoNotDisturb => "DoNotDisturb",
+            TSAvailability::Offline => "Offline",
+            TSAvailability::Focusing => "Focusing",
+            TSAvailability::Presenting => "Presenting",
+            TSAvailability::InAMeeting => "InAMeeting",
+            TSAvailability::OnThePhone => "OnThePhone",
+            TSAvailability::Unknown => "Unknown",
+        }
+    }
+
+    pub fn is_known(&self) -> bool {
+        !matches!(self, TSAvailability::Unknown | TSAvailability::Offline)
+    }
+
+    /// Teams only logs the availability the user picked, the call changes what others see, as in Teams.ps1:
+    /// ```text
+    /// If ($Activity -eq $taInACall -And $Status -eq $tsDoNotDisturb) {
+    ///     $Status = $tsPresenting
+    /// } ElseIf ($Activity -eq $taInACall) {
+    ///     $Status = $tsInAMeeting
+    /// }
+    /// ```
+    pub fn with_activity(self, is_in_a_call: bool) -> Self {
+        match (self, is_in_a_call) {
+            // nothing is known about the user, a call does not change that
+            (TSAvailability::Unknown, _) => self,
+            (TSAvailability::DoNotDisturb, true) => TSAvailability::Presenting,
+            (_, true) => TSAvailability::InAMeeting,
+            (_, false) => self,
+        }
+    }
+}
+
+impl fmt::Display for TSAvailability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::teams_log::states::TSAvailability;
+
+    #[test]
+    fn from_log_known_value_will_parse() {
+        assert_eq!(TSAvailability::from_log("BeRightBack"), TSAvailability::BeRightBack);
+        assert_eq!(TSAvailability::from_log("DoNotDisturb"), TSAvailability::DoNotDisturb);
+    }
+
+    #[test]
+    fn from_log_unknown_value_will_be_unknown() {
+        assert_eq!(TSAvailability::from_log("Sleeping"), TSAvailability::Unknown);
+        assert_eq!(TSAvailability::from_log(""), TSAvailability::Unknown);
+    }
+
+    #[test]
+    fn with_activity_in_a_call_and_do_not_disturb_will_be_presenting() {
+        assert_eq!(
+            TSAvailability::DoNotDisturb.with_activity(true),
+            TSAvailability::Presenting
+        );
+    }
+
+    #[test]
+    fn with_activity_in_a_call_will_be_in_a_meeting() {
+        assert_eq!(TSAvailability::Busy.with_activity(true), TSAvailability::InAMeeting);
+        assert_eq!(TSAvailability::Unknown.with_activity(true), TSAvailability::Unknown);
+    }
+
+    #[test]
+    fn with_activity_not_in_a_call_will_not_change() {
+        assert_eq!(
+            TSAvailability::DoNotDisturb.with_activity(false),
+            TSAvailability::DoNotDisturb
+        );
+    }
+}
@@ -1,75 +1,52 @@
-use std::fs;
+use crate::teams_log::file_locator::locate_latest_log;
+use crate::teams_log::file_notifier::{FileNotifier, LogEvent};
+use crate::teams_log::parser::LogParser;
+use crate::teams_log::states::TSAvailability;
 use std::path::PathBuf;
-use crate::teams_log::file_locator::{locate_latest_log, TEAMS_PREFIX};
-use crate::teams_log::file_notifier::FileNotifier;
-use crate::teams_log::parser::get_last_state;
 
 // higher-level unit, will coordinate between locator, notifier and parser
 pub struct Watcher {
-    teams_base_path: PathBuf,
+    teams_log_path: PathBuf,
     file_notifier: FileNotifier,
 }
 
 impl Watcher {
-    pub fn new() -> Self {
-        let file_notifier = FileNotifier::new().unwrap();
-        let mut app_data = std::env::var("APPDATA").unwrap();
-        app_data = app_data.replace("\\Roaming", "");
-        let teams_base_path: PathBuf = [r"C:\", &app_data, "Local", "Packages", "MSTeams_8wekyb3d8bbwe", "LocalCache", "Microsoft", "MSTeams", "Logs"].iter().collect();
-        Self { teams_base_path, file_notifier }
+    pub fn new(teams_log_path: PathBuf) -> anyhow::Result<Self> {
+        // watched before the first read, so nothing written in between is missed
+        let file_notifier = FileNotifier::watch(teams_log_path.clone())?;
+        Ok(Self {
+            teams_log_path,
+            file_notifier,
+        })
     }
 
-    pub async fn watch_teams_files(&mut self) -> anyhow::Result<()> {
-        let mut latest_log_file = locate_latest_log(&self.teams_base_path)?;
-
-        let (mut last_state, mut file_position) = get_last_state(latest_log_file.as_ref(), 0).unwrap();
-        // todo: call API here
-        fs::write(r"C:\Users\antoi\Documents\teams_log.txt", last_state.unwrap_or("None".to_string())).unwrap();
-
-        // self.file_notifier.watch(latest_log_file.as_ref())?;
-        self.file_notifier.watch(Some(&self.teams_base_path))?;
-
-        // todo: this only gets triggered when refreshing the file in Notepad++
-        while let Some(res) = self.file_notifier.rx.recv().await {
-            match res {
-                Ok(res) => {
-                    if (res.paths.len() == 0) || res.paths.first().is_none() {
-                        continue;
-                    }
-
-                    let changed_file = res.paths.first().unwrap();
-
-                    if changed_file.file_name().is_none() || changed_file.file_name().unwrap().to_str().is_none() {
-                        continue;
-                    }
-
-                    if changed_file.file_name().unwrap().to_str().unwrap().starts_with(TEAMS_PREFIX) {
-                        // Is it a different file then the latest log file?
-                        if Some(changed_file) != latest_log_file.as_ref() {
-                            let new_latest_log_file = locate_latest_log(&self.teams_base_path)?;
-
-                            if new_latest_log_file != latest_log_file {
-                                self.file_notifier.unwatch(latest_log_file.as_ref())?;
-                                // latest_log_file = res.paths.first()?.into_path_buf();
-                                latest_log_file = new_latest_log_file;
-                                self.file_notifier.watch(latest_log_file.as_ref())?;
-                                file_position = 0;
-                            }
-                        }
-
-                        let (new_last_state, mew_file_position) = get_last_state(latest_log_file.as_ref(), file_position).unwrap();
-                        last_state = new_last_state;
-                        file_position = mew_file_position;
-
-                        if last_state.is_some() {
-                            // todo: we will be calling API here
-                            fs::write(r"C:\Users\antoi\Documents\teams_log.txt", last_state.clone().unwrap_or("None".to_string())).unwrap();
-                            println!("{}", last_state.clone().unwrap());
-                        }
-                    }
+    /// Sends the last availability found in the latest log, then every new one written by Teams
+    pub async fn watch_teams_files(&mut self, mut on_availability: impl FnMut(TSAvailability)) -> anyhow::Result<()> {
+        let mut parser = locate_latest_log(&self.teams_log_path)?.map(LogParser::new);
+
+        if let Some(last_state) = read_last_state(parser.as_mut())? {
+            on_availability(last_state);
+        }
+
+        while let Some(event) = self.file_notifier.rx.recv().await {
+            let is_current_log = |path: &PathBuf| parser.as_ref().is_some_and(|parser| parser.path() == path);
+
+            let relocate = match &event {
+                LogEvent::LogAppended(path) => !is_current_log(path),
+                LogEvent::NewLogFile(_) => true,
+                LogEvent::LogRemoved(path) => is_current_log(path),
+            };
+
+            if relocate {
+                let latest_log_file = locate_latest_log(&self.teams_log_path)?;
+
+                if latest_log_file.as_deref() != parser.as_ref().map(LogParser::path) {
+                    parser = latest_log_file.map(LogParser::new);
                 }
-                // todo: not sure I should exit here
-                Err(res) => break,
+            }
+
+            if let Some(last_state) = read_last_state(parser.as_mut())? {
+                on_availability(last_state);
             }
         }
 
@@ -77,13 +54,80 @@ impl Watcher {
     }
 }
 
+fn read_last_state(parser: Option<&mut LogParser>) -> anyhow::Result<Option<TSAvailability>> {
+    match parser {
+        Some(parser) => parser.read_last_state(),
+        None => Ok(None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::teams_log::states::TSAvailability;
     use crate::teams_log::watcher::Watcher;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::time::Duration;
+    use tokio::sync::mpsc::unbounded_channel;
+    use tokio::time::timeout;
+
+    const LOG_FILE_NAME: &str = "MSTeams_2024-02-07_14-47-09.05.log";
+
+    fn availability_line(availability: &str) -> String {
+        format!(
+            "2024-02-07T14:47:20.301Z Info UserPresenceAction: {{source: 1, availability: {}}}\n",
+            availability
+        )
+    }
 
     #[tokio::test]
     async fn end_to_end_test() {
-        let mut watcher = Watcher::new();
-        watcher.watch_teams_files().await.unwrap();
+        let folder = tempfile::tempdir().unwrap();
+        let log_path = folder.path().join(LOG_FILE_NAME);
+        fs::write(&log_path, availability_line("Available")).unwrap();
+
+        let (sender, mut receiver) = unbounded_channel();
+        let mut watcher = Watcher::new(folder.path().to_path_buf()).unwrap();
+        tokio::spawn(async move {
+            let _ = watcher
+                .watch_teams_files(|availability| sender.send(availability).unwrap())
+                .await;
+        });
+
+        let first = timeout(Duration::from_secs(5), receiver.recv()).await.unwrap();
+        assert_eq!(first.unwrap(), TSAvailability::Available);
+
+        let mut log_file = OpenOptions::new().append(true).open(&log_path).unwrap();
+        log_file.write_all(availability_line("Busy").as_bytes()).unwrap();
+
+        let second = timeout(Duration::from_secs(5), receiver.recv()).await.unwrap();
+        assert_eq!(second.unwrap(), TSAvailability::Busy);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn new_log_file_will_be_followed() {
+        let folder = tempfile::tempdir().unwrap();
+        fs::write(folder.path().join(LOG_FILE_NAME), availability_line("Available")).unwrap();
+
+        let (sender, mut receiver) = unbounded_channel();
+        let mut watcher = Watcher::new(folder.path().to_path_buf()).unwrap();
+        tokio::spawn(async move {
+            let _ = watcher
+                .watch_teams_files(|availability| sender.send(availability).unwrap())
+                .await;
+        });
+
+        let first = timeout(Duration::from_secs(5), receiver.recv()).await.unwrap();
+        assert_eq!(first.unwrap(), TSAvailability::Available);
+
+        // Teams starts a new file when restarted
+        fs::write(
+            folder.path().join("MSTeams_2024-02-08_09-00-00.00.log"),
+            availability_line("Away"),
+        )
+        .unwrap();
+
+        let second = timeout(Duration::from_secs(5), receiver.recv()).await.unwrap();
+        assert_eq!(second.unwrap(), TSAvailability::Away);
+    }
+}
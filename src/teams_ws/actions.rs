@@ -0,0 +1,195 @@
+use anyhow::anyhow;
+use json::object;
+use std::fmt;
+use std::str::FromStr;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+
+const API_VERSION: &str = "2.0.0";
+
+pub type ActionResponder = oneshot::Sender<anyhow::Result<()>>;
+
+pub struct ActionRequest {
+    pub action: TeamsAction,
+    // None when the caller does not wait for Teams' answer, failures are then only logged and sent to listeners
+    pub responder: Option<ActionResponder>,
+}
+
+#[derive(Clone)]
+pub struct ActionSender {
+    sender: UnboundedSender<ActionRequest>,
+}
+
+impl ActionSender {
+    pub fn new() -> (Self, UnboundedReceiver<ActionRequest>) {
+        let (sender, receiver) = unbounded_channel();
+        (Self { sender }, receiver)
+    }
+
+    pub fn send(&self, action: TeamsAction) -> anyhow::Result<()> {
+        self.sender
+            .send(ActionRequest { action, responder: None })
+            .map_err(|_| anyhow!("The Teams connection is gone"))
+    }
+
+    /// Waits for Teams to accept or reject the action
+    pub async fn request(&self, action: TeamsAction) -> anyhow::Result<()> {
+        let (responder, response) = oneshot::channel();
+        self.sender
+            .send(ActionRequest { action, responder: Some(responder) })
+            .map_err(|_| anyhow!("The Teams connection is gone"))?;
+
+        response
+            .await
+            .map_err(|_| anyhow!("The Teams connection closed before answering"))?
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TeamsReaction {
+    Like,
+    Love,
+    Applause,
+    Laugh,
+    Wow,
+}
+
+impl TeamsReaction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TeamsReaction::Like => "like",
+            TeamsReaction::Love => "love",
+            TeamsReaction::Applause => "applause",
+            TeamsReaction::Laugh => "laugh",
+            TeamsReaction::Wow => "wow",
+        }
+    }
+}
+
+impl FromStr for TeamsReaction {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "like" => Ok(TeamsReaction::Like),
+            "love" => Ok(TeamsReaction::Love),
+            "applause" => Ok(TeamsReaction::Applause),
+            "laugh" => Ok(TeamsReaction::Laugh),
+            "wow" => Ok(TeamsReaction::Wow),
+            _ => Err(anyhow!("Unknown reaction '{}'", value)),
+        }
+    }
+}
+
+/// Actions accepted by the local Teams API (v2)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TeamsAction {
+    ToggleMute,
+    ToggleVideo,
+    ToggleHand,
+    ToggleBackgroundBlur,
+    LeaveCall,
+    SendReaction(TeamsReaction),
+    QueryState,
+}
+
+impl TeamsAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TeamsAction::ToggleMute => "toggle-mute",
+            TeamsAction::ToggleVideo => "toggle-video",
+            TeamsAction::ToggleHand => "toggle-hand",
+            TeamsAction::ToggleBackgroundBlur => "toggle-background-blur",
+            TeamsAction::LeaveCall => "leave-call",
+            TeamsAction::SendReaction(_) => "send-reaction",
+            TeamsAction::QueryState => "query-state",
+        }
+    }
+
+    pub fn to_message(self, request_id: u32) -> String {
+        let mut message = object! {
+            "requestId": request_id,
+            "apiVersion": API_VERSION,
+            "action": self.as_str(),
+        };
+
+        if let TeamsAction::SendReaction(reaction) = self {
+            message["parameters"] = object! { "type": reaction.as_str() };
+        }
+
+        message.dump()
+    }
+}
+
+/// Parses the action names used by Teams, reactions are written as `send-reaction:<type>`
+impl FromStr for TeamsAction {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim().to_lowercase();
+
+        if let Some(reaction) = value.strip_prefix("send-reaction:") {
+            return Ok(TeamsAction::SendReaction(reaction.parse()?));
+        }
+
+        match value.as_str() {
+            "toggle-mute" => Ok(TeamsAction::ToggleMute),
+            "toggle-video" => Ok(TeamsAction::ToggleVideo),
+            "toggle-hand" => Ok(TeamsAction::ToggleHand),
+            "toggle-background-blur" => Ok(TeamsAction::ToggleBackgroundBlur),
+            "leave-call" => Ok(TeamsAction::LeaveCall),
+            "query-state" => Ok(TeamsAction::QueryState),
+            _ => Err(anyhow!("Unknown action '{}'", value)),
+        }
+    }
+}
+
+impl fmt::Display for TeamsAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TeamsAction::SendReaction(reaction) => {
+                write!(f, "{} ({})", self.as_str(), reaction.as_str())
+            }
+            _ => write!(f, "{}", self.as_str()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::teams_ws::actions::{TeamsAction, TeamsReaction};
+
+    #[test]
+    fn to_message_toggle_mute_will_match_teams_format() {
+        let message = TeamsAction::ToggleMute.to_message(1);
+        assert_eq!(
+            message,
+            r#"{"requestId":1,"apiVersion":"2.0.0","action":"toggle-mute"}"#
+        );
+    }
+
+    #[test]
+    fn to_message_send_reaction_will_include_reaction_type() {
+        let message = TeamsAction::SendReaction(TeamsReaction::Applause).to_message(7);
+        let parsed = json::parse(&message).unwrap();
+        assert_eq!(parsed["requestId"], 7);
+        assert_eq!(parsed["action"], "send-reaction");
+        assert_eq!(parsed["parameters"]["type"], "applause");
+    }
+
+    #[test]
+    fn from_str_action_names_will_parse() {
+        assert_eq!("toggle-video".parse::<TeamsAction>().unwrap(), TeamsAction::ToggleVideo);
+        assert_eq!(" Leave-Call ".parse::<TeamsAction>().unwrap(), TeamsAction::LeaveCall);
+        assert_eq!(
+            "send-reaction:wow".parse::<TeamsAction>().unwrap(),
+            TeamsAction::SendReaction(TeamsReaction::Wow)
+        );
+    }
+
+    #[test]
+    fn from_str_unknown_action_will_fail() {
+        assert!("toggle-everything".parse::<TeamsAction>().is_err());
+        assert!("send-reaction:boo".parse::<TeamsAction>().is_err());
+    }
+}
@@ -1,56 +1,39 @@
+use crate::listener_workers::ListenerWorkers;
+use crate::teams_ws::actions::{ActionRequest, TeamsAction};
 use crate::teams_ws::configuration::{
     change_teams_configuration, TeamsConfiguration, TEAMS, TEAMS_API_TOKEN,
 };
-use crate::teams_ws::states::TeamsStates;
-use crate::traits::Listener;
-use anyhow::Context;
-use futures_util::{future, pin_mut, SinkExt, StreamExt};
+use crate::teams_ws::pending_requests::{PendingRequests, REQUEST_TIMEOUT};
+use crate::teams_ws::states::{TeamsStateKey, TeamsStates};
+use anyhow::{anyhow, Context};
+use futures_util::future::Either;
+use futures_util::{future, pin_mut, Sink, SinkExt, StreamExt};
 use json::JsonValue;
 use log::{error, info};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time;
-use std::time::Duration;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use std::time::Instant;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_tungstenite::tungstenite::{self, protocol::Message};
+use tokio_tungstenite::connect_async;
 
 const JSON_MEETING_UPDATE: &str = "meetingUpdate";
-const JSON_MEETING_STATE: &str = "meetingState";
-const JSON_IS_MUTED: &str = "isMuted";
-const JSON_IS_VIDEO_ON: &str = "isVideoOn";
-const JSON_IS_HAND_RAISED: &str = "isHandRaised";
-const JSON_IS_IN_MEETING: &str = "isInMeeting";
-const JSON_IS_RECORDING_ON: &str = "isRecordingOn";
-const JSON_IS_BACKGROUND_BLURRED: &str = "isBackgroundBlurred";
-const JSON_IS_SHARING: &str = "isSharing";
-const JSON_HAS_UNREAD_MESSAGES: &str = "hasUnreadMessages";
 const JSON_TOKEN_REFRESH: &str = "tokenRefresh";
+const JSON_REQUEST_ID: &str = "requestId";
+const JSON_RESPONSE: &str = "response";
+const JSON_ERROR_MSG: &str = "errorMsg";
+const JSON_RESPONSE_SUCCESS: &str = "Success";
 
 pub struct TeamsAPI {
     pub teams_states: Arc<TeamsStates>,
     pub url: String,
+    // without one, Teams shows its pairing prompt when it receives an action
+    has_token: bool,
 }
 
 impl TeamsAPI {
-    pub fn new(conf: &TeamsConfiguration) -> Self {
-        let teams_states = Arc::new(TeamsStates {
-            is_muted: AtomicBool::new(false),
-            prev_is_muted: AtomicBool::new(false),
-            is_video_on: AtomicBool::new(false),
-            prev_is_video_on: AtomicBool::new(false),
-            is_hand_raised: AtomicBool::new(false),
-            prev_is_hand_raised: AtomicBool::new(false),
-            is_in_meeting: AtomicBool::new(false),
-            prev_is_in_meeting: AtomicBool::new(false),
-            is_recording_on: AtomicBool::new(false),
-            prev_is_recording_on: AtomicBool::new(false),
-            is_background_blurred: AtomicBool::new(false),
-            prev_is_background_blurred: AtomicBool::new(false),
-            is_sharing: AtomicBool::new(false),
-            prev_is_sharing: AtomicBool::new(false),
-            has_unread_messages: AtomicBool::new(false),
-            prev_has_unread_messages: AtomicBool::new(false),
-        });
-
+    pub fn new(conf: &TeamsConfiguration, teams_states: Arc<TeamsStates>) -> Self {
         let api_token = if !conf.api_token.is_empty() {
             format!("token={}&", &conf.api_token)
         } else {
@@ -61,14 +44,18 @@ impl TeamsAPI {
             url = conf.url,
             api_token = api_token);
 
-        Self { teams_states, url }
+        Self {
+            teams_states,
+            url,
+            has_token: !conf.api_token.is_empty(),
+        }
     }
 
     pub async fn start_listening(
         &self,
-        listener: Arc<Mutex<Box<dyn Listener>>>,
+        listener_workers: &ListenerWorkers,
         is_running: Arc<AtomicBool>,
-        toggle_mute: Arc<AtomicBool>,
+        actions: &mut UnboundedReceiver<ActionRequest>,
     ) -> anyhow::Result<()> {
         let url_local = url::Url::parse(&self.url)?;
         let (ws_stream, _) = connect_async(url_local)
@@ -76,6 +63,43 @@ impl TeamsAPI {
             .with_context(|| "Failed to connect")?;
         let (mut write, read) = ws_stream.split();
         let force_update = Arc::new(AtomicBool::new(true));
+        let pending_requests = Mutex::new(PendingRequests::new());
+        listener_workers.notify_teams_connected(true);
+        self.set_teams_connected(listener_workers, true);
+
+        // Actions requested while Teams was unreachable are stale by now, toggling them later would surprise the user,
+        // except for the tray's Toggle Mute that is used to pair with Teams before having a token
+        let mut pairing_request = None;
+        while let Ok(request) = actions.try_recv() {
+            let request = if !self.has_token && request.action == TeamsAction::ToggleMute {
+                match pairing_request.replace(request) {
+                    Some(previous) => previous,
+                    None => continue,
+                }
+            } else {
+                request
+            };
+
+            info!("Discarding {} requested while Teams was unreachable", request.action);
+
+            if let Some(responder) = request.responder {
+                let _ = responder.send(Err(anyhow!("Teams was unreachable")));
+            }
+        }
+
+        // Teams only pushes updates on changes, so we ask for the current state right away
+        let mut request_id: u32 = 1;
+        pending_requests.lock().unwrap().insert(request_id, TeamsAction::QueryState, REQUEST_TIMEOUT, None);
+        write
+            .send(Message::text(TeamsAction::QueryState.to_message(request_id)))
+            .await
+            .with_context(|| "Unable to query the Teams state")?;
+
+        if let Some(request) = pairing_request {
+            request_id += 1;
+            send_request(&mut write, &pending_requests, request_id, request).await?;
+        }
+
         let ws_to_parser = {
             read.for_each(|message| async {
                 if message.is_ok() {
@@ -85,9 +109,10 @@ impl TeamsAPI {
 
                     let parse_result = parse_data_and_notify_listener(
                         &json,
-                        listener.clone(),
+                        listener_workers,
                         self.teams_states.clone(),
                         force_update.clone(),
+                        &pending_requests,
                     )
                         .await;
 
@@ -99,95 +124,142 @@ impl TeamsAPI {
         };
 
         let running_future = async {
-            let one_second = time::Duration::from_secs(1);
+            let mut running_check = tokio::time::interval(time::Duration::from_secs(1));
 
             while is_running.load(Ordering::Relaxed) {
-                tokio::time::sleep(one_second).await;
-
-                if toggle_mute.load(Ordering::Relaxed) {
-                    let msg = Message::text(
-                        r#"{"requestId":1,"apiVersion":"2.0.0","action":"toggle-mute"}"#,
-                    );
+                tokio::select! {
+                    request = actions.recv() => {
+                        let Some(request) = request else { break };
+                        request_id += 1;
+                        send_request(&mut write, &pending_requests, request_id, request).await?;
+                    }
+                    _ = running_check.tick() => {
+                        let expired = pending_requests.lock().unwrap().expire(Instant::now());
 
-                    write.send(msg).await.unwrap();
-                    toggle_mute.swap(false, Ordering::Relaxed);
+                        for action in expired {
+                            report_action_failure(listener_workers, &action, "Teams did not answer in time");
+                        }
+                    }
                 }
             }
 
             info!("Application close requested");
+            anyhow::Ok(())
         };
 
         pin_mut!(running_future, ws_to_parser);
-        future::select(running_future, ws_to_parser).await;
-        Ok(())
+        let result = match future::select(running_future, ws_to_parser).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Ok(()),
+        };
+
+        let unanswered = pending_requests.lock().unwrap().fail_all("The Teams connection closed before answering");
+        for action in unanswered {
+            report_action_failure(listener_workers, &action, "The Teams connection closed before answering");
+        }
+        listener_workers.notify_teams_connected(false);
+        self.set_teams_connected(listener_workers, false);
+
+        result
+    }
+
+    // the presence depends on the connection, as the websocket states are stale once disconnected
+    fn set_teams_connected(&self, listener_workers: &ListenerWorkers, is_connected: bool) {
+        let changed = self
+            .teams_states
+            .update(|snapshot| snapshot.with_teams_connected(is_connected));
+
+        if !changed.is_empty() {
+            listener_workers.notify_changed(false);
+        }
     }
 }
 
-async fn update_value(
-    teams_state_value: &AtomicBool,
-    answer: &JsonValue,
-    json_value_name: &str,
-) -> bool {
-    let new_value = answer[JSON_MEETING_UPDATE][JSON_MEETING_STATE][json_value_name]
+async fn send_request(
+    write: &mut (impl Sink<Message, Error = tungstenite::Error> + Unpin),
+    pending_requests: &Mutex<PendingRequests>,
+    request_id: u32,
+    request: ActionRequest,
+) -> anyhow::Result<()> {
+    info!("Sending {} to Teams (request {})", request.action, request_id);
+    pending_requests.lock().unwrap().insert(
+        request_id,
+        request.action,
+        REQUEST_TIMEOUT,
+        request.responder,
+    );
+
+    write
+        .send(Message::text(request.action.to_message(request_id)))
+        .await
+        .with_context(|| format!("Unable to send {} to Teams", request.action))
+}
+
+fn report_action_failure(listener_workers: &ListenerWorkers, action: &TeamsAction, reason: &str) {
+    error!("Teams could not complete {}: {}", action, reason);
+    listener_workers.notify_action_failed(action, reason);
+}
+
+/// Returns the request id with the success or error message, if the JSON is an answer to one of our requests
+fn parse_response(answer: &JsonValue) -> Option<(u32, Result<(), String>)> {
+    let request_id = answer[JSON_REQUEST_ID].as_u32()?;
+
+    if answer.has_key(JSON_ERROR_MSG) {
+        return Some((request_id, Err(answer[JSON_ERROR_MSG].to_string())));
+    }
+
+    let response = answer[JSON_RESPONSE].as_str()?;
+    if response == JSON_RESPONSE_SUCCESS {
+        Some((request_id, Ok(())))
+    } else {
+        Some((request_id, Err(response.to_string())))
+    }
+}
+
+fn read_value(answer: &JsonValue, key: TeamsStateKey, section: &str, name: &str) -> bool {
+    answer[JSON_MEETING_UPDATE][section][name]
         .as_bool()
         .unwrap_or_else(|| {
-            error!("Unable to locate {} variable in JSON", json_value_name);
+            // a missing permission is not granted
+            if !key.is_permission() {
+                error!("Unable to locate {} variable in JSON", name);
+            }
             false
-        });
-
-    teams_state_value.swap(new_value, Ordering::Relaxed) != new_value
+        })
 }
 
 async fn parse_data_and_notify_listener(
     json: &str,
-    listener: Arc<Mutex<Box<dyn Listener>>>,
+    listener_workers: &ListenerWorkers,
     teams_states: Arc<TeamsStates>,
     force_update: Arc<AtomicBool>,
+    pending_requests: &Mutex<PendingRequests>,
 ) -> anyhow::Result<()> {
     let answer = json::parse(&json.to_string()).unwrap_or(json::parse("{}").unwrap());
 
-    if answer.has_key(JSON_MEETING_UPDATE) {
-        let mut has_changed = update_value(&teams_states.is_muted, &answer, JSON_IS_MUTED).await;
-        has_changed |= update_value(&teams_states.is_video_on, &answer, JSON_IS_VIDEO_ON).await;
-        has_changed |=
-            update_value(&teams_states.is_hand_raised, &answer, JSON_IS_HAND_RAISED).await;
-        has_changed |= update_value(&teams_states.is_in_meeting, &answer, JSON_IS_IN_MEETING).await;
-        has_changed |=
-            update_value(&teams_states.is_recording_on, &answer, JSON_IS_RECORDING_ON).await;
-        has_changed |= update_value(
-            &teams_states.is_background_blurred,
-            &answer,
-            JSON_IS_BACKGROUND_BLURRED,
-        )
-            .await;
-        has_changed |= update_value(&teams_states.is_sharing, &answer, JSON_IS_SHARING).await;
-        has_changed |= update_value(
-            &teams_states.has_unread_messages,
-            &answer,
-            JSON_HAS_UNREAD_MESSAGES,
-        )
-            .await;
+    if let Some((request_id, result)) = parse_response(&answer) {
+        let action = pending_requests.lock().unwrap().resolve(request_id, result.clone());
+
+        match (action, result) {
+            (Some(action), Err(error)) => report_action_failure(listener_workers, &action, &error),
+            (Some(action), Ok(())) => info!("Teams completed {} (request {})", action, request_id),
+            (None, _) => info!("Ignoring answer to unknown or timed out request {}", request_id),
+        }
+    } else if answer.has_key(JSON_MEETING_UPDATE) {
+        let changed = teams_states.update(|mut snapshot| {
+            for key in TeamsStateKey::ALL {
+                if let Some((section, name)) = key.json_path() {
+                    snapshot = snapshot.with(key, read_value(&answer, key, section, name));
+                }
+            }
+            snapshot
+        });
+        let has_changed = !changed.is_empty();
 
         let force_update = force_update.swap(false, Ordering::Relaxed);
 
         if force_update || has_changed {
-            // Issue!: This will only run once regardless of MAX_RETRIES
-            // for some reason after a reconnect the notify_changed will get a pass no matter what
-            const MAX_RETRIES: i32 = 3;
-            for i in 1..MAX_RETRIES {
-                let result = listener.lock().unwrap().notify_changed(&teams_states, force_update).await;
-
-                if result.is_ok() || (i == MAX_RETRIES) {
-                    result?;
-                    break;
-                }
-                // we will try to reconnect if the connection failed
-                else if i < MAX_RETRIES {
-                    error!("{}: Reconnecting and retrying...", result.unwrap_err());
-                    tokio::time::sleep(Duration::from_secs(1)).await;
-                    listener.lock().unwrap().reconnect();
-                }
-            }
+            listener_workers.notify_changed(force_update);
         }
     } else if answer.has_key(JSON_TOKEN_REFRESH) && !answer[JSON_TOKEN_REFRESH].is_empty() {
         change_teams_configuration(
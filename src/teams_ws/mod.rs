@@ -1,3 +1,5 @@
+pub mod actions;
 pub mod api;
 pub mod configuration;
+pub mod pending_requests;
 pub mod states;
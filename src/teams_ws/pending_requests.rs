@@ -0,0 +1,119 @@
+use crate::teams_ws::actions::{ActionResponder, TeamsAction};
+use anyhow::anyhow;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct PendingRequest {
+    action: TeamsAction,
+    deadline: Instant,
+    responder: Option<ActionResponder>,
+}
+
+/// Requests sent to Teams that are waiting on their `response`/`errorMsg` answer
+pub struct PendingRequests {
+    requests: HashMap<u32, PendingRequest>,
+}
+
+impl PendingRequests {
+    pub fn new() -> Self {
+        Self {
+            requests: HashMap::new(),
+        }
+    }
+
+    pub fn insert(
+        &mut self,
+        request_id: u32,
+        action: TeamsAction,
+        timeout: Duration,
+        responder: Option<ActionResponder>,
+    ) {
+        self.requests.insert(
+            request_id,
+            PendingRequest {
+                action,
+                deadline: Instant::now() + timeout,
+                responder,
+            },
+        );
+    }
+
+    /// Completes the request and returns its action, or None if the request is unknown (i.e. already timed out)
+    pub fn resolve(&mut self, request_id: u32, result: Result<(), String>) -> Option<TeamsAction> {
+        let request = self.requests.remove(&request_id)?;
+
+        if let Some(responder) = request.responder {
+            // the caller may have stopped waiting, which is fine
+            let _ = responder.send(result.map_err(|error| anyhow!(error)));
+        }
+
+        Some(request.action)
+    }
+
+    /// Fails every request past its deadline and returns their actions
+    pub fn expire(&mut self, now: Instant) -> Vec<TeamsAction> {
+        let expired: Vec<u32> = self
+            .requests
+            .iter()
+            .filter(|(_, request)| request.deadline <= now)
+            .map(|(request_id, _)| *request_id)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|request_id| {
+                self.resolve(request_id, Err("Teams did not answer in time".to_string()))
+            })
+            .collect()
+    }
+
+    pub fn fail_all(&mut self, reason: &str) -> Vec<TeamsAction> {
+        let request_ids: Vec<u32> = self.requests.keys().copied().collect();
+
+        request_ids
+            .into_iter()
+            .filter_map(|request_id| self.resolve(request_id, Err(reason.to_string())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::teams_ws::actions::TeamsAction;
+    use crate::teams_ws::pending_requests::PendingRequests;
+    use std::time::{Duration, Instant};
+    use tokio::sync::oneshot;
+
+    #[test]
+    fn resolve_known_request_will_answer_caller() {
+        let mut pending_requests = PendingRequests::new();
+        let (responder, mut response) = oneshot::channel();
+        pending_requests.insert(3, TeamsAction::ToggleVideo, Duration::from_secs(10), Some(responder));
+
+        let action = pending_requests.resolve(3, Err("Not in a meeting".to_string()));
+
+        assert_eq!(action, Some(TeamsAction::ToggleVideo));
+        let result = response.try_recv().unwrap();
+        assert_eq!(result.unwrap_err().to_string(), "Not in a meeting");
+    }
+
+    #[test]
+    fn resolve_unknown_request_will_return_none() {
+        let mut pending_requests = PendingRequests::new();
+        assert_eq!(pending_requests.resolve(42, Ok(())), None);
+    }
+
+    #[test]
+    fn expire_will_only_fail_requests_past_their_deadline() {
+        let mut pending_requests = PendingRequests::new();
+        pending_requests.insert(1, TeamsAction::ToggleMute, Duration::from_secs(0), None);
+        pending_requests.insert(2, TeamsAction::LeaveCall, Duration::from_secs(60), None);
+
+        let expired = pending_requests.expire(Instant::now());
+
+        assert_eq!(expired, vec![TeamsAction::ToggleMute]);
+        assert_eq!(pending_requests.resolve(2, Ok(())), Some(TeamsAction::LeaveCall));
+    }
+}
@@ -1,20 +1,320 @@
-use std::sync::atomic::AtomicBool;
+use crate::presence::fusion::PresencePrecedence;
+use crate::presence::states::Presence;
+use crate::teams_log::states::TSAvailability;
+use crate::utils::bool_to_str;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
 
+const JSON_MEETING_STATE: &str = "meetingState";
+const JSON_MEETING_PERMISSIONS: &str = "meetingPermissions";
+
+/// Every state reported by Teams, through its websocket or its logs, listeners iterate over these instead of enumerating the states by hand
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TeamsStateKey {
+    IsMuted,
+    IsVideoOn,
+    IsHandRaised,
+    IsInMeeting,
+    IsRecordingOn,
+    IsBackgroundBlurred,
+    IsSharing,
+    HasUnreadMessages,
+    CanToggleMute,
+    CanToggleVideo,
+    CanToggleHand,
+    CanToggleBlur,
+    CanLeave,
+    CanReact,
+    CanToggleShareTray,
+    CanToggleChat,
+    CanStopSharing,
+    CanPair,
+    // from the Teams logs
+    Availability,
+    // derived from all of the above
+    Presence,
+}
+
+impl TeamsStateKey {
+    pub const ALL: [TeamsStateKey; 20] = [
+        TeamsStateKey::IsMuted,
+        TeamsStateKey::IsVideoOn,
+        TeamsStateKey::IsHandRaised,
+        TeamsStateKey::IsInMeeting,
+        TeamsStateKey::IsRecordingOn,
+        TeamsStateKey::IsBackgroundBlurred,
+        TeamsStateKey::IsSharing,
+        TeamsStateKey::HasUnreadMessages,
+        TeamsStateKey::CanToggleMute,
+        TeamsStateKey::CanToggleVideo,
+        TeamsStateKey::CanToggleHand,
+        TeamsStateKey::CanToggleBlur,
+        TeamsStateKey::CanLeave,
+        TeamsStateKey::CanReact,
+        TeamsStateKey::CanToggleShareTray,
+        TeamsStateKey::CanToggleChat,
+        TeamsStateKey::CanStopSharing,
+        TeamsStateKey::CanPair,
+        TeamsStateKey::Availability,
+        TeamsStateKey::Presence,
+    ];
+
+    /// Name used in our own outputs
+    pub fn name(&self) -> &'static str {
+        match self {
+            TeamsStateKey::IsMuted => "is_muted",
+            TeamsStateKey::IsVideoOn => "is_video_on",
+            TeamsStateKey::IsHandRaised => "is_hand_raised",
+            TeamsStateKey::IsInMeeting => "is_in_meeting",
+            TeamsStateKey::IsRecordingOn => "is_recording_on",
+            TeamsStateKey::IsBackgroundBlurred => "is_background_blurred",
+            TeamsStateKey::IsSharing => "is_sharing",
+            TeamsStateKey::HasUnreadMessages => "has_unread_messages",
+            TeamsStateKey::CanToggleMute => "can_toggle_mute",
+            TeamsStateKey::CanToggleVideo => "can_toggle_video",
+            TeamsStateKey::CanToggleHand => "can_toggle_hand",
+            TeamsStateKey::CanToggleBlur => "can_toggle_blur",
+            TeamsStateKey::CanLeave => "can_leave",
+            TeamsStateKey::CanReact => "can_react",
+            TeamsStateKey::CanToggleShareTray => "can_toggle_share_tray",
+            TeamsStateKey::CanToggleChat => "can_toggle_chat",
+            TeamsStateKey::CanStopSharing => "can_stop_sharing",
+            TeamsStateKey::CanPair => "can_pair",
+            TeamsStateKey::Availability => "availability",
+            TeamsStateKey::Presence => "presence",
+        }
+    }
+
+    pub fn is_binary(&self) -> bool {
+        !matches!(self, TeamsStateKey::Availability | TeamsStateKey::Presence)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        TeamsStateKey::ALL.into_iter().find(|key| key.name() == name)
+    }
+
+    /// Newer than the meeting states, Teams leaves them out of some messages
+    pub fn is_permission(&self) -> bool {
+        matches!(self.json_path(), Some((JSON_MEETING_PERMISSIONS, _)))
+    }
+
+    /// Section and name of the value in the Teams `meetingUpdate` message, None when it comes from elsewhere
+    pub fn json_path(&self) -> Option<(&'static str, &'static str)> {
+        let path = match self {
+            TeamsStateKey::IsMuted => (JSON_MEETING_STATE, "isMuted"),
+            TeamsStateKey::IsVideoOn => (JSON_MEETING_STATE, "isVideoOn"),
+            TeamsStateKey::IsHandRaised => (JSON_MEETING_STATE, "isHandRaised"),
+            TeamsStateKey::IsInMeeting => (JSON_MEETING_STATE, "isInMeeting"),
+            TeamsStateKey::IsRecordingOn => (JSON_MEETING_STATE, "isRecordingOn"),
+            TeamsStateKey::IsBackgroundBlurred => (JSON_MEETING_STATE, "isBackgroundBlurred"),
+            TeamsStateKey::IsSharing => (JSON_MEETING_STATE, "isSharing"),
+            TeamsStateKey::HasUnreadMessages => (JSON_MEETING_STATE, "hasUnreadMessages"),
+            TeamsStateKey::CanToggleMute => (JSON_MEETING_PERMISSIONS, "canToggleMute"),
+            TeamsStateKey::CanToggleVideo => (JSON_MEETING_PERMISSIONS, "canToggleVideo"),
+            TeamsStateKey::CanToggleHand => (JSON_MEETING_PERMISSIONS, "canToggleHand"),
+            TeamsStateKey::CanToggleBlur => (JSON_MEETING_PERMISSIONS, "canToggleBlur"),
+            TeamsStateKey::CanLeave => (JSON_MEETING_PERMISSIONS, "canLeave"),
+            TeamsStateKey::CanReact => (JSON_MEETING_PERMISSIONS, "canReact"),
+            TeamsStateKey::CanToggleShareTray => (JSON_MEETING_PERMISSIONS, "canToggleShareTray"),
+            TeamsStateKey::CanToggleChat => (JSON_MEETING_PERMISSIONS, "canToggleChat"),
+            TeamsStateKey::CanStopSharing => (JSON_MEETING_PERMISSIONS, "canStopSharing"),
+            TeamsStateKey::CanPair => (JSON_MEETING_PERMISSIONS, "canPair"),
+            TeamsStateKey::Availability | TeamsStateKey::Presence => return None,
+        };
+
+        Some(path)
+    }
+}
+
+/// Immutable copy of the Teams states at a point in time
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TeamsSnapshot {
+    values: BTreeMap<TeamsStateKey, bool>,
+    // as logged by Teams, before applying the call activity
+    availability: TSAvailability,
+    teams_connected: bool,
+    // fused by TeamsStates on every update, as it depends on the configured precedence
+    presence: Presence,
+}
+
+impl TeamsSnapshot {
+    /// The availability and presence are considered on when they are known and not offline
+    pub fn get(&self, key: TeamsStateKey) -> bool {
+        match key {
+            TeamsStateKey::Availability => self.availability().is_known(),
+            TeamsStateKey::Presence => self.presence != Presence::Offline,
+            _ => self.values.get(&key).copied().unwrap_or(false),
+        }
+    }
+
+    /// Availability as seen by others, a meeting overrides the one picked by the user
+    pub fn availability(&self) -> TSAvailability {
+        self.availability.with_activity(self.get(TeamsStateKey::IsInMeeting))
+    }
+
+    /// Availability as written in the Teams logs
+    pub fn logged_availability(&self) -> TSAvailability {
+        self.availability
+    }
+
+    /// Whether the websocket is connected, its states are stale otherwise
+    pub fn teams_connected(&self) -> bool {
+        self.teams_connected
+    }
+
+    /// Value sent to the listeners, `on`/`off` for the binary states
+    pub fn state_str(&self, key: TeamsStateKey) -> String {
+        match key {
+            TeamsStateKey::Availability => self.availability().to_string(),
+            TeamsStateKey::Presence => self.presence.to_string(),
+            _ => bool_to_str(self.get(key)),
+        }
+    }
+
+    pub fn with(mut self, key: TeamsStateKey, value: bool) -> Self {
+        self.values.insert(key, value);
+        self
+    }
+
+    pub fn with_availability(mut self, availability: TSAvailability) -> Self {
+        self.availability = availability;
+        self
+    }
+
+    pub fn with_teams_connected(mut self, teams_connected: bool) -> Self {
+        self.teams_connected = teams_connected;
+        self
+    }
+
+    /// Returns the keys whose value differs from the previous snapshot
+    pub fn diff(&self, previous: &TeamsSnapshot) -> Vec<TeamsStateKey> {
+        TeamsStateKey::ALL
+            .into_iter()
+            .filter(|key| match key {
+                TeamsStateKey::Availability => self.availability() != previous.availability(),
+                TeamsStateKey::Presence => self.presence != previous.presence,
+                _ => self.get(*key) != previous.get(*key),
+            })
+            .collect()
+    }
+}
+
+/// Latest snapshot, shared between the Teams websocket, the Teams logs and the listeners
+#[derive(Default)]
 pub struct TeamsStates {
-    pub is_muted: AtomicBool,
-    pub prev_is_muted: AtomicBool,
-    pub is_video_on: AtomicBool,
-    pub prev_is_video_on: AtomicBool,
-    pub is_hand_raised: AtomicBool,
-    pub prev_is_hand_raised: AtomicBool,
-    pub is_in_meeting: AtomicBool,
-    pub prev_is_in_meeting: AtomicBool,
-    pub is_recording_on: AtomicBool,
-    pub prev_is_recording_on: AtomicBool,
-    pub is_background_blurred: AtomicBool,
-    pub prev_is_background_blurred: AtomicBool,
-    pub is_sharing: AtomicBool,
-    pub prev_is_sharing: AtomicBool,
-    pub has_unread_messages: AtomicBool,
-    pub prev_has_unread_messages: AtomicBool,
+    current: RwLock<TeamsSnapshot>,
+    precedence: PresencePrecedence,
+}
+
+impl TeamsStates {
+    pub fn new(precedence: PresencePrecedence) -> Self {
+        Self {
+            current: RwLock::new(TeamsSnapshot::default()),
+            precedence,
+        }
+    }
+
+    pub fn snapshot(&self) -> TeamsSnapshot {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Changes the snapshot under the lock, so the websocket and the logs do not overwrite each other's states,
+    /// and returns what changed
+    pub fn update(&self, change: impl FnOnce(TeamsSnapshot) -> TeamsSnapshot) -> Vec<TeamsStateKey> {
+        let mut current = self.current.write().unwrap();
+        let mut snapshot = change(current.clone());
+        snapshot.presence = self.precedence.fuse(&snapshot);
+        let changed = snapshot.diff(&current);
+        *current = snapshot;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::teams_log::states::TSAvailability;
+    use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey, TeamsStates};
+
+    #[test]
+    fn is_permission_will_only_match_meeting_permissions() {
+        assert!(TeamsStateKey::CanToggleMute.is_permission());
+        assert!(!TeamsStateKey::IsMuted.is_permission());
+        assert!(!TeamsStateKey::Presence.is_permission());
+    }
+
+    #[test]
+    fn diff_will_return_changed_keys_only() {
+        let previous = TeamsSnapshot::default()
+            .with(TeamsStateKey::IsMuted, true)
+            .with(TeamsStateKey::IsInMeeting, true);
+        let current = previous
+            .clone()
+            .with(TeamsStateKey::IsMuted, false)
+            .with(TeamsStateKey::CanLeave, true);
+
+        assert_eq!(
+            current.diff(&previous),
+            vec![TeamsStateKey::IsMuted, TeamsStateKey::CanLeave]
+        );
+    }
+
+    #[test]
+    fn diff_missing_value_will_equal_false() {
+        let previous = TeamsSnapshot::default();
+        let current = TeamsSnapshot::default().with(TeamsStateKey::IsSharing, false);
+
+        assert!(current.diff(&previous).is_empty());
+    }
+
+    #[test]
+    fn update_will_return_changes_from_stored_snapshot() {
+        let teams_states = TeamsStates::default();
+        teams_states.update(|snapshot| snapshot.with(TeamsStateKey::IsVideoOn, true));
+
+        let changed = teams_states.update(|snapshot| snapshot.with(TeamsStateKey::IsVideoOn, true));
+
+        assert!(changed.is_empty());
+        assert!(teams_states.snapshot().get(TeamsStateKey::IsVideoOn));
+    }
+
+    #[test]
+    fn update_availability_will_keep_other_states() {
+        let teams_states = TeamsStates::default();
+        teams_states.update(|snapshot| snapshot.with(TeamsStateKey::IsMuted, true));
+
+        let changed = teams_states
+            .update(|snapshot| snapshot.with_availability(TSAvailability::Busy));
+
+        assert_eq!(changed, vec![TeamsStateKey::Availability, TeamsStateKey::Presence]);
+        assert!(teams_states.snapshot().get(TeamsStateKey::IsMuted));
+        assert_eq!(teams_states.snapshot().state_str(TeamsStateKey::Availability), "Busy");
+    }
+
+    #[test]
+    fn diff_joining_meeting_will_change_availability() {
+        let previous = TeamsSnapshot::default().with_availability(TSAvailability::Available);
+        let current = previous.clone().with(TeamsStateKey::IsInMeeting, true);
+
+        assert_eq!(
+            current.diff(&previous),
+            vec![TeamsStateKey::IsInMeeting, TeamsStateKey::Availability]
+        );
+        assert_eq!(current.availability(), TSAvailability::InAMeeting);
+    }
+
+    #[test]
+    fn update_disconnecting_will_change_presence() {
+        let teams_states = TeamsStates::default();
+        teams_states.update(|snapshot| {
+            snapshot
+                .with_teams_connected(true)
+                .with(TeamsStateKey::IsInMeeting, true)
+        });
+        assert_eq!(teams_states.snapshot().state_str(TeamsStateKey::Presence), "In Call");
+
+        let changed = teams_states.update(|snapshot| snapshot.with_teams_connected(false));
+
+        assert_eq!(changed, vec![TeamsStateKey::Presence]);
+        assert_eq!(teams_states.snapshot().state_str(TeamsStateKey::Presence), "Offline");
+    }
 }
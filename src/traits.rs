@@ -1,11 +1,16 @@
-use crate::teams_ws::states::TeamsStates;
+use crate::teams_ws::actions::TeamsAction;
+use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
 use async_trait::async_trait;
 
 pub trait StopController {}
 
 // todo: convert to Rust built-in once 1.75 is released
 #[async_trait]
-pub trait Listener {
-    async fn notify_changed(&self, teams_states: &TeamsStates, force_update: bool) -> anyhow::Result<()>;
+pub trait Listener: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// `changed` holds every key when a full update is needed, i.e. on start or after a failure
+    async fn notify_changed(&self, snapshot: &TeamsSnapshot, changed: &[TeamsStateKey]) -> anyhow::Result<()>;
+    async fn notify_action_failed(&self, action: &TeamsAction, reason: &str) -> anyhow::Result<()>;
+    async fn notify_teams_connected(&self, is_connected: bool) -> anyhow::Result<()>;
     fn reconnect(&mut self);
 }
@@ -1,4 +1,6 @@
+use crate::teams_ws::actions::{ActionSender, TeamsAction, TeamsReaction};
 use crate::traits::StopController;
+use log::error;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tray_item::{IconSource, TrayItem};
@@ -8,13 +10,29 @@ pub struct TrayWindows {
 }
 
 impl TrayWindows {
-    pub fn new(is_running: Arc<AtomicBool>, toggle_mute: Arc<AtomicBool>) -> Self {
+    pub fn new(is_running: Arc<AtomicBool>, action_sender: ActionSender) -> Self {
         let mut tray = TrayItem::new("Teams Status", IconSource::Resource("default-icon")).unwrap();
 
-        tray.add_menu_item("Toggle Mute", move || {
-            toggle_mute.store(true, Ordering::Relaxed);
-        })
-        .unwrap();
+        add_action_item(&mut tray, "Toggle Mute", TeamsAction::ToggleMute, &action_sender);
+        add_action_item(&mut tray, "Toggle Video", TeamsAction::ToggleVideo, &action_sender);
+        add_action_item(&mut tray, "Toggle Hand", TeamsAction::ToggleHand, &action_sender);
+        add_action_item(
+            &mut tray,
+            "Toggle Background Blur",
+            TeamsAction::ToggleBackgroundBlur,
+            &action_sender,
+        );
+        add_action_item(&mut tray, "Leave Call", TeamsAction::LeaveCall, &action_sender);
+
+        for (label, reaction) in [
+            ("React - Like", TeamsReaction::Like),
+            ("React - Love", TeamsReaction::Love),
+            ("React - Applause", TeamsReaction::Applause),
+            ("React - Laugh", TeamsReaction::Laugh),
+            ("React - Wow", TeamsReaction::Wow),
+        ] {
+            add_action_item(&mut tray, label, TeamsAction::SendReaction(reaction), &action_sender);
+        }
 
         tray.add_menu_item("Quit", move || {
             is_running.store(false, Ordering::Relaxed);
@@ -27,10 +45,21 @@ impl TrayWindows {
 
 impl StopController for TrayWindows {}
 
+fn add_action_item(tray: &mut TrayItem, label: &str, action: TeamsAction, action_sender: &ActionSender) {
+    let action_sender = action_sender.clone();
+
+    tray.add_menu_item(label, move || {
+        if action_sender.send(action).is_err() {
+            error!("Unable to queue {}, the Teams connection is gone", action);
+        }
+    })
+    .unwrap();
+}
+
 pub fn create_tray(
     is_running: Arc<AtomicBool>,
-    toggle_mute: Arc<AtomicBool>,
+    action_sender: ActionSender,
 ) -> Box<dyn StopController> {
-    let tray = TrayWindows::new(is_running, toggle_mute);
+    let tray = TrayWindows::new(is_running, action_sender);
     Box::new(tray)
 }
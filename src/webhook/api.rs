@@ -0,0 +1,280 @@
+use crate::teams_ws::actions::TeamsAction;
+use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
+use crate::traits::Listener;
+use crate::webhook::configuration::WebhookConfiguration;
+use crate::webhook::template::render_body;
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use reqwest::{Client, Method, StatusCode};
+use serde_json::json;
+use sha2::Sha256;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const MAX_ATTEMPTS: u32 = 4;
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const SIGNATURE_HEADER: &str = "X-Teams-Status-Signature";
+// requests that could not be delivered, one JSON per line, so they can be replayed by hand
+const DEAD_LETTER_FILE_NAME: &str = "webhook_dead_letters.jsonl";
+
+/// Sends the changed states to any HTTP endpoint, e.g. Node-RED, n8n or a Slack bot
+pub struct WebhookApi {
+    webhook_configuration: WebhookConfiguration,
+    method: Method,
+    client: Client,
+    retry_delay: Duration,
+    dead_letter_path: PathBuf,
+}
+
+impl WebhookApi {
+    pub fn new(webhook_configuration: WebhookConfiguration) -> anyhow::Result<Self> {
+        let method = Method::from_bytes(webhook_configuration.method.to_uppercase().as_bytes())
+            .map_err(|_| anyhow!("Invalid method '{}'", webhook_configuration.method))?;
+        let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+
+        Ok(Self {
+            webhook_configuration,
+            method,
+            client,
+            retry_delay: INITIAL_RETRY_DELAY,
+            dead_letter_path: PathBuf::from(DEAD_LETTER_FILE_NAME),
+        })
+    }
+
+    async fn send(&self, body: &str) -> anyhow::Result<StatusCode> {
+        let conf = &self.webhook_configuration;
+        let mut request = self
+            .client
+            .request(self.method.clone(), &conf.url)
+            .header("Content-Type", "application/json");
+
+        // added after the default, so the configuration can override it
+        for (name, value) in &conf.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+
+        if !conf.hmac_secret.is_empty() {
+            request = request.header(SIGNATURE_HEADER, sign(&conf.hmac_secret, body).as_str());
+        }
+
+        let response = request.body(body.to_string()).send().await?;
+        Ok(response.status())
+    }
+
+    /// Retries with a backoff when the endpoint is unreachable or failing, the body goes to the dead-letter log
+    /// when it is rejected or still failing after the last attempt
+    async fn deliver(&self, body: String) {
+        let name = &self.webhook_configuration.name;
+        let mut retry_delay = self.retry_delay;
+        let mut attempt = 1;
+
+        let reason = loop {
+            let (reason, is_retryable) = match self.send(&body).await {
+                Ok(status) if status.is_success() => return,
+                Ok(status) => (
+                    format!("The endpoint answered {}", status),
+                    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+                ),
+                Err(error) => (format!("{:#}", error), true),
+            };
+
+            if !is_retryable || attempt == MAX_ATTEMPTS {
+                break reason;
+            }
+
+            warn!(
+                "Webhook {}: Attempt {} failed, retrying in {} ms: {}",
+                name,
+                attempt,
+                retry_delay.as_millis(),
+                reason
+            );
+            tokio::time::sleep(retry_delay).await;
+            retry_delay *= 2;
+            attempt += 1;
+        };
+
+        error!("Webhook {}: Giving up after {} attempt(s): {}", name, attempt, reason);
+
+        if let Err(error) = self.write_dead_letter(&body, &reason) {
+            error!("Webhook {}: Unable to write the dead-letter log: {:#}", name, error);
+        }
+    }
+
+    fn write_dead_letter(&self, body: &str, reason: &str) -> anyhow::Result<()> {
+        let dead_letter = json!({
+            "timestamp": Utc::now().to_rfc3339(),
+            "webhook": self.webhook_configuration.name,
+            "method": self.method.as_str(),
+            "url": self.webhook_configuration.url,
+            "body": body,
+            "reason": reason,
+        });
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.dead_letter_path)
+            .with_context(|| format!("Unable to open {}", self.dead_letter_path.display()))?;
+        writeln!(file, "{}", dead_letter)?;
+
+        info!(
+            "Webhook {}: Request saved to {}",
+            self.webhook_configuration.name,
+            self.dead_letter_path.display()
+        );
+        Ok(())
+    }
+}
+
+/// `sha256=<hex>` HMAC of the body, so the endpoint can check the request comes from us
+fn sign(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    let signature: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    format!("sha256={}", signature)
+}
+
+#[async_trait]
+impl Listener for WebhookApi {
+    fn name(&self) -> &'static str {
+        "Webhook"
+    }
+
+    // failures are handled by the retries and the dead-letter log, so an endpoint being down does not
+    // hold back the next changes
+    async fn notify_changed(&self, snapshot: &TeamsSnapshot, changed: &[TeamsStateKey]) -> anyhow::Result<()> {
+        let body = render_body(
+            &self.webhook_configuration.body_template,
+            snapshot,
+            changed,
+            Utc::now(),
+        );
+
+        self.deliver(body).await;
+        Ok(())
+    }
+
+    async fn notify_action_failed(&self, _: &TeamsAction, _: &str) -> anyhow::Result<()> {
+        // the endpoints only receive states
+        Ok(())
+    }
+
+    async fn notify_teams_connected(&self, _: bool) -> anyhow::Result<()> {
+        // the presence goes offline with Teams, which is sent as a change
+        Ok(())
+    }
+
+    fn reconnect(&mut self) {
+        // nothing to reconnect, every request opens its own connection when needed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
+    use crate::traits::Listener;
+    use crate::webhook::api::{sign, WebhookApi, SIGNATURE_HEADER};
+    use crate::webhook::configuration::create_webhook_configuration;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use serde_json::Value;
+    use std::collections::VecDeque;
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tempfile::TempDir;
+    use tokio::net::TcpListener;
+
+    #[derive(Clone, Default)]
+    struct Endpoint {
+        // answered in order, then 200
+        statuses: Arc<Mutex<VecDeque<StatusCode>>>,
+        received: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+    }
+
+    async fn receive(State(endpoint): State<Endpoint>, headers: HeaderMap, body: String) -> StatusCode {
+        endpoint.received.lock().unwrap().push((headers, body));
+        endpoint.statuses.lock().unwrap().pop_front().unwrap_or(StatusCode::OK)
+    }
+
+    async fn create_test_webhook(statuses: Vec<StatusCode>) -> (WebhookApi, Endpoint, TempDir) {
+        let endpoint = Endpoint::default();
+        endpoint.statuses.lock().unwrap().extend(statuses);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = Router::new().route("/hook", post(receive)).with_state(endpoint.clone());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let mut webhook_configuration = create_webhook_configuration("Test");
+        webhook_configuration.url = format!("http://{}/hook", address);
+        webhook_configuration.hmac_secret = "secret".to_string();
+        webhook_configuration
+            .headers
+            .push(("X-Source".to_string(), "teams".to_string()));
+
+        let folder = tempfile::tempdir().unwrap();
+        let mut webhook = WebhookApi::new(webhook_configuration).unwrap();
+        webhook.retry_delay = Duration::from_millis(10);
+        webhook.dead_letter_path = folder.path().join("dead_letters.jsonl");
+
+        (webhook, endpoint, folder)
+    }
+
+    #[test]
+    fn sign_will_match_rfc_4231_vector() {
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn notify_changed_server_error_will_be_retried() {
+        let (webhook, endpoint, _folder) = create_test_webhook(vec![StatusCode::BAD_GATEWAY]).await;
+        let snapshot = TeamsSnapshot::default().with(TeamsStateKey::IsMuted, true);
+
+        webhook.notify_changed(&snapshot, &[TeamsStateKey::IsMuted]).await.unwrap();
+
+        let received = endpoint.received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (headers, body) = &received[1];
+        assert_eq!(headers["X-Source"], "teams");
+        assert_eq!(headers[SIGNATURE_HEADER], sign("secret", body).as_str());
+        let body: Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["changes"]["is_muted"], true);
+        assert!(!webhook.dead_letter_path.exists());
+    }
+
+    #[tokio::test]
+    async fn notify_changed_rejected_will_go_to_dead_letter_log() {
+        let (webhook, endpoint, _folder) = create_test_webhook(vec![StatusCode::BAD_REQUEST]).await;
+
+        webhook
+            .notify_changed(&TeamsSnapshot::default(), &[TeamsStateKey::IsVideoOn])
+            .await
+            .unwrap();
+
+        assert_eq!(endpoint.received.lock().unwrap().len(), 1);
+        let dead_letters = fs::read_to_string(&webhook.dead_letter_path).unwrap();
+        let dead_letter: Value = serde_json::from_str(dead_letters.lines().next().unwrap()).unwrap();
+        assert_eq!(dead_letter["webhook"], "Test");
+        assert_eq!(dead_letter["method"], "POST");
+        assert!(dead_letter["reason"].as_str().unwrap().contains("400"));
+        assert!(dead_letter["body"].as_str().unwrap().contains("is_video_on"));
+    }
+}
@@ -0,0 +1,32 @@
+// followed by the name of the webhook, e.g. `Webhook - Node-RED`
+pub const WEBHOOK: &str = "Webhook - ";
+pub const WEBHOOK_URL: &str = "URL";
+pub const WEBHOOK_METHOD: &str = "Method";
+// followed by the name of the header, e.g. `Header - Authorization`
+pub const WEBHOOK_HEADER: &str = "Header - ";
+pub const WEBHOOK_HMAC_SECRET: &str = "HMAC Secret";
+pub const WEBHOOK_BODY_TEMPLATE: &str = "Body Template";
+pub const WEBHOOK_METHOD_DEFAULT: &str = "POST";
+pub const WEBHOOK_BODY_TEMPLATE_DEFAULT: &str =
+    r#"{"timestamp": "{{timestamp}}", "changes": {{changes}}, "state": {{state}}}"#;
+
+pub struct WebhookConfiguration {
+    pub name: String,
+    pub url: String,
+    pub method: String,
+    pub headers: Vec<(String, String)>,
+    // the body is not signed when empty
+    pub hmac_secret: String,
+    pub body_template: String,
+}
+
+pub fn create_webhook_configuration(name: &str) -> WebhookConfiguration {
+    WebhookConfiguration {
+        name: name.to_string(),
+        url: "".to_string(),
+        method: WEBHOOK_METHOD_DEFAULT.to_string(),
+        headers: Vec::new(),
+        hmac_secret: "".to_string(),
+        body_template: WEBHOOK_BODY_TEMPLATE_DEFAULT.to_string(),
+    }
+}
@@ -0,0 +1,92 @@
+use crate::http_api::api::state_to_json;
+use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
+use chrono::{DateTime, Utc};
+use regex::{Captures, Regex};
+use serde_json::{Map, Value};
+use std::sync::OnceLock;
+
+const PLACEHOLDER_TIMESTAMP: &str = "timestamp";
+const PLACEHOLDER_CHANGES: &str = "changes";
+const PLACEHOLDER_STATE: &str = "state";
+
+fn placeholder_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(r"\{\{\s*([a-z_]+)\s*\}\}").expect("the placeholder regex is valid"))
+}
+
+/// Replaces the placeholders of the template:
+/// - `{{changes}}` and `{{state}}`, JSON objects of the changed fields and of every field
+/// - `{{timestamp}}`, RFC 3339
+/// - `{{<field>}}`, e.g. `{{presence}}` or `{{is_muted}}`, without quotes so it can be used within a string
+///
+/// Unknown placeholders are left as is.
+pub fn render_body(
+    template: &str,
+    snapshot: &TeamsSnapshot,
+    changed: &[TeamsStateKey],
+    timestamp: DateTime<Utc>,
+) -> String {
+    placeholder_regex()
+        .replace_all(template, |captures: &Captures| match &captures[1] {
+            PLACEHOLDER_TIMESTAMP => timestamp.to_rfc3339(),
+            PLACEHOLDER_CHANGES => fields_to_json(snapshot, changed).to_string(),
+            PLACEHOLDER_STATE => fields_to_json(snapshot, &TeamsStateKey::ALL).to_string(),
+            name => match TeamsStateKey::from_name(name) {
+                Some(key) => match state_to_json(snapshot, key) {
+                    Value::String(value) => value,
+                    value => value.to_string(),
+                },
+                None => captures[0].to_string(),
+            },
+        })
+        .into_owned()
+}
+
+fn fields_to_json(snapshot: &TeamsSnapshot, keys: &[TeamsStateKey]) -> Value {
+    let values: Map<String, Value> = keys
+        .iter()
+        .map(|key| (key.name().to_string(), state_to_json(snapshot, *key)))
+        .collect();
+
+    Value::Object(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::teams_ws::states::{TeamsSnapshot, TeamsStateKey};
+    use crate::webhook::configuration::WEBHOOK_BODY_TEMPLATE_DEFAULT;
+    use crate::webhook::template::render_body;
+    use chrono::{TimeZone, Utc};
+    use serde_json::Value;
+
+    #[test]
+    fn render_body_default_template_will_be_json_with_changed_fields() {
+        let snapshot = TeamsSnapshot::default().with(TeamsStateKey::IsMuted, true);
+        let timestamp = Utc.with_ymd_and_hms(2024, 2, 7, 14, 52, 10).unwrap();
+
+        let body = render_body(WEBHOOK_BODY_TEMPLATE_DEFAULT, &snapshot, &[TeamsStateKey::IsMuted], timestamp);
+        let body: Value = serde_json::from_str(&body).unwrap();
+
+        assert_eq!(body["timestamp"], "2024-02-07T14:52:10+00:00");
+        assert_eq!(body["changes"], serde_json::json!({ "is_muted": true }));
+        assert_eq!(body["state"]["is_video_on"], false);
+        assert_eq!(body["state"]["presence"], "Offline");
+    }
+
+    #[test]
+    fn render_body_field_placeholder_will_be_inserted_without_quotes() {
+        let snapshot = TeamsSnapshot::default().with(TeamsStateKey::IsVideoOn, true);
+
+        let body = render_body(
+            r#"{"text": "Presence: {{ presence }}", "video": {{is_video_on}}, "other": "{{unknown}}"}"#,
+            &snapshot,
+            &[],
+            Utc::now(),
+        );
+
+        assert_eq!(
+            body,
+            r#"{"text": "Presence: Offline", "video": true, "other": "{{unknown}}"}"#
+        );
+    }
+}